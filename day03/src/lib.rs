@@ -2,16 +2,97 @@
 
 use std::collections::HashSet;
 
-fn char_to_number(char: char) -> u32 {
-    if char.is_lowercase() {
-        char as u32 - 'a' as u32 + 1
-    } else if char.is_uppercase() {
-        char as u32 - 'A' as u32 + 1 + 26
+fn char_to_number(char: char) -> Option<u32> {
+    if char.is_ascii_lowercase() {
+        Some(char as u32 - 'a' as u32 + 1)
+    } else if char.is_ascii_uppercase() {
+        Some(char as u32 - 'A' as u32 + 1 + 26)
     } else {
-        0
+        None
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RucksackError {
+    OddItemCount { line: usize, count: usize },
+    InvalidItem { line: usize, item: char },
+    NoDuplicate { line: usize },
+    MultipleDuplicates { line: usize, items: Vec<char> },
+    IncompleteGroup { first_line: usize, size: usize },
+    NoBadge { first_line: usize },
+    MultipleBadges { first_line: usize, items: Vec<char> },
+}
+
+fn sorted_items(set: HashSet<char>) -> Vec<char> {
+    let mut items = Vec::from_iter(set);
+    items.sort();
+    items
+}
+
+/// Checks every rucksack and every group of three for the invariants the
+/// puzzle relies on. Line numbers are 1-based.
+pub fn validate(input: &str) -> Vec<RucksackError> {
+    let mut errors = Vec::new();
+    let lines = input.lines().collect::<Vec<_>>();
+
+    for (index, l) in lines.iter().enumerate() {
+        let line = index + 1;
+        let count = l.chars().count();
+
+        if count % 2 != 0 {
+            errors.push(RucksackError::OddItemCount { line, count });
+        }
+
+        for item in l.chars().filter(|c| char_to_number(*c).is_none()) {
+            errors.push(RucksackError::InvalidItem { line, item });
+        }
+
+        let (left, right) = l.split_at(l.char_indices().nth(count / 2).map_or(l.len(), |(i, _)| i));
+        let left_set: HashSet<char> = HashSet::from_iter(left.chars());
+        let right_set: HashSet<char> = HashSet::from_iter(right.chars());
+        let duplicates = sorted_items(left_set.intersection(&right_set).copied().collect());
+
+        match duplicates.len() {
+            0 => errors.push(RucksackError::NoDuplicate { line }),
+            1 => {}
+            _ => errors.push(RucksackError::MultipleDuplicates {
+                line,
+                items: duplicates,
+            }),
+        }
+    }
+
+    for (index, group) in lines.chunks(3).enumerate() {
+        let first_line = index * 3 + 1;
+
+        if group.len() < 3 {
+            errors.push(RucksackError::IncompleteGroup {
+                first_line,
+                size: group.len(),
+            });
+            continue;
+        }
+
+        let badges = group
+            .iter()
+            .map(|l| HashSet::from_iter(l.chars()))
+            .reduce(|l: HashSet<char>, r| l.intersection(&r).copied().collect())
+            .map(sorted_items)
+            .unwrap_or_default();
+
+        match badges.len() {
+            0 => errors.push(RucksackError::NoBadge { first_line }),
+            1 => {}
+            _ => errors.push(RucksackError::MultipleBadges {
+                first_line,
+                items: badges,
+            }),
+        }
+    }
+
+    errors
+}
+
 pub fn process(input: String) -> Option<u32> {
     let lines = input.lines();
 
@@ -26,7 +107,7 @@ pub fn process(input: String) -> Option<u32> {
 
             let duplication = intersection.into_iter().next();
 
-            duplication.map(char_to_number)
+            duplication.and_then(char_to_number)
         })
         .sum()
 }
@@ -45,7 +126,7 @@ pub fn process_groups(input: String) -> Option<u32> {
                     l.intersection(&r).copied().collect()
                 })
                 .and_then(|h| h.into_iter().next())
-                .map(char_to_number)
+                .and_then(char_to_number)
         })
         .sum()
 }
@@ -65,4 +146,38 @@ mod tests {
     fn test_badges() {
         assert_eq!(process_groups(MOVES.to_string()), Some(70));
     }
+
+    #[test]
+    fn test_validate_valid() {
+        assert_eq!(validate(MOVES), vec![]);
+    }
+
+    #[test]
+    fn test_validate_invalid() {
+        let input = "abcab\nxBcd\nab1ab\nxyzw";
+
+        assert_eq!(
+            validate(input),
+            vec![
+                RucksackError::OddItemCount { line: 1, count: 5 },
+                RucksackError::MultipleDuplicates {
+                    line: 1,
+                    items: vec!['a', 'b']
+                },
+                RucksackError::NoDuplicate { line: 2 },
+                RucksackError::OddItemCount { line: 3, count: 5 },
+                RucksackError::InvalidItem { line: 3, item: '1' },
+                RucksackError::MultipleDuplicates {
+                    line: 3,
+                    items: vec!['a', 'b']
+                },
+                RucksackError::NoDuplicate { line: 4 },
+                RucksackError::NoBadge { first_line: 1 },
+                RucksackError::IncompleteGroup {
+                    first_line: 4,
+                    size: 1
+                },
+            ]
+        );
+    }
 }
@@ -1,28 +1,266 @@
 #![feature(iter_array_chunks)]
 
+use num::PrimInt;
 use std::ops::RangeInclusive;
 
+/// A set of integers stored as sorted, disjoint and non-adjacent inclusive ranges.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct IntervalSet<T> {
+    ranges: Vec<RangeInclusive<T>>,
+}
+
+impl<T: PrimInt> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet { ranges: Vec::new() }
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = &RangeInclusive<T>> {
+        self.ranges.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.ranges
+            .binary_search_by(|r| {
+                if r.end() < value {
+                    std::cmp::Ordering::Less
+                } else if r.start() > value {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn min(&self) -> Option<T> {
+        self.ranges.first().map(|r| *r.start())
+    }
+
+    pub fn max(&self) -> Option<T> {
+        self.ranges.last().map(|r| *r.end())
+    }
+
+    pub fn insert(&mut self, range: RangeInclusive<T>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let (mut start, mut end) = range.into_inner();
+        // ranges ending right before start can be merged as well
+        let first = self
+            .ranges
+            .partition_point(|r| *r.end() < start && start - *r.end() > T::one());
+        let last = self
+            .ranges
+            .partition_point(|r| *r.start() <= end || *r.start() - end == T::one());
+
+        if first < last {
+            start = start.min(*self.ranges[first].start());
+            end = end.max(*self.ranges[last - 1].end());
+        }
+
+        self.ranges.splice(first..last, [start..=end]);
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for r in other.ranges() {
+            result.insert(r.clone());
+        }
+        result
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let a = &self.ranges[i];
+            let b = &other.ranges[j];
+            let start = *a.start().max(b.start());
+            let end = *a.end().min(b.end());
+
+            if start <= end {
+                ranges.push(start..=end);
+            }
+
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { ranges }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let mut j = 0;
+
+        for r in self.ranges() {
+            let mut start = *r.start();
+            let end = *r.end();
+            let mut remaining = true;
+
+            while j < other.ranges.len() && *other.ranges[j].end() < start {
+                j += 1;
+            }
+
+            let mut k = j;
+            while remaining && k < other.ranges.len() && *other.ranges[k].start() <= end {
+                let cut = &other.ranges[k];
+                if *cut.start() > start {
+                    ranges.push(start..=(*cut.start() - T::one()));
+                }
+                if *cut.end() >= end {
+                    remaining = false;
+                } else {
+                    start = *cut.end() + T::one();
+                    k += 1;
+                }
+            }
+
+            if remaining {
+                ranges.push(start..=end);
+            }
+        }
+
+        IntervalSet { ranges }
+    }
+
+    /// The ranges between min() and max() that are not covered by the set.
+    pub fn gaps(&self) -> Self {
+        IntervalSet {
+            ranges: self
+                .ranges
+                .windows(2)
+                .map(|w| (*w[0].end() + T::one())..=(*w[1].start() - T::one()))
+                .collect(),
+        }
+    }
+
+    /// Number of integers contained in the set.
+    pub fn covered_len(&self) -> T {
+        self.ranges
+            .iter()
+            .fold(T::zero(), |acc, r| acc + (*r.end() - *r.start()) + T::one())
+    }
+}
+
+impl<T: PrimInt> FromIterator<RangeInclusive<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = RangeInclusive<T>>>(iter: I) -> Self {
+        let mut set = IntervalSet::new();
+        for r in iter {
+            set.insert(r);
+        }
+        set
+    }
+}
+
+impl<T: PrimInt> From<RangeInclusive<T>> for IntervalSet<T> {
+    fn from(value: RangeInclusive<T>) -> Self {
+        IntervalSet::from_iter([value])
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Debug)]
 pub enum Overlap {
     Partial,
     Fully,
 }
 
-fn overlapping(a: &RangeInclusive<u32>, b: &RangeInclusive<u32>) -> Option<Overlap> {
-    let left_b_in_a = a.contains(&b.clone().min()?);
-    let right_b_in_a = a.contains(&b.clone().max()?);
-    let left_a_in_b = b.contains(&a.clone().min()?);
-    let right_a_in_b = b.contains(&a.clone().max()?);
+/// The section ranges assigned to the elves of one line.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Assignment {
+    pub ranges: Vec<RangeInclusive<u32>>,
+}
+
+impl Assignment {
+    pub fn one_contains_all(&self) -> bool {
+        let (Some(min), Some(max)) = (
+            self.ranges.iter().map(|r| *r.start()).min(),
+            self.ranges.iter().map(|r| *r.end()).max(),
+        ) else {
+            return false;
+        };
+
+        self.ranges
+            .iter()
+            .any(|r| *r.start() == min && *r.end() == max)
+    }
+
+    pub fn pairwise_overlapping(&self) -> bool {
+        // intervals on a line pairwise overlap exactly when they share a common point
+        !self.common().is_empty()
+    }
+
+    /// The largest number of ranges covering a single section.
+    pub fn max_simultaneous_overlap(&self) -> usize {
+        let mut events = self
+            .ranges
+            .iter()
+            .filter(|r| !r.is_empty())
+            .flat_map(|r| [(*r.start() as u64, 1), (*r.end() as u64 + 1, -1)])
+            .collect::<Vec<(u64, i32)>>();
+        events.sort();
+
+        events
+            .iter()
+            .scan(0, |active, (_, delta)| {
+                *active += delta;
+                Some(*active as usize)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The sections covered by every range.
+    pub fn common(&self) -> IntervalSet<u32> {
+        self.ranges
+            .iter()
+            .map(|r| IntervalSet::from(r.clone()))
+            .reduce(|a, b| a.intersection(&b))
+            .unwrap_or_default()
+    }
 
-    if (left_b_in_a && right_b_in_a) || (left_a_in_b && right_a_in_b) {
-        Some(Overlap::Fully)
-    } else if left_b_in_a || right_b_in_a || left_a_in_b || right_a_in_b {
-        Some(Overlap::Partial)
-    } else {
-        None
+    /// Generalizes the pairwise overlap: `Fully` if one range contains all the
+    /// others, `Partial` if all ranges pairwise overlap.
+    pub fn overlap(&self) -> Option<Overlap> {
+        if self.ranges.iter().any(|r| r.is_empty()) {
+            None
+        } else if self.one_contains_all() {
+            Some(Overlap::Fully)
+        } else if self.pairwise_overlapping() {
+            Some(Overlap::Partial)
+        } else {
+            None
+        }
     }
 }
 
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LineOverlap {
+    pub line: usize,
+    pub kind: Option<Overlap>,
+    pub range: Option<RangeInclusive<u32>>,
+    pub length: u32,
+    pub max_simultaneous: usize,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Coverage {
+    pub covered: IntervalSet<u32>,
+    pub covered_len: u32,
+    pub overlapped: IntervalSet<u32>,
+    pub overlapped_len: u32,
+    pub gaps: IntervalSet<u32>,
+}
+
 fn parse_range(string: &str) -> Option<RangeInclusive<u32>> {
     let (start_str, end_str) = string.split_once('-')?;
     let s = str::parse::<u32>(start_str).ok();
@@ -31,16 +269,23 @@ fn parse_range(string: &str) -> Option<RangeInclusive<u32>> {
     Some(s?..=e?)
 }
 
+fn parse_assignment(line: &str) -> Option<Assignment> {
+    Some(Assignment {
+        ranges: line.split(',').map(parse_range).collect::<Option<_>>()?,
+    })
+}
+
+pub fn parse_assignments(input: &str) -> Option<Vec<Assignment>> {
+    input.lines().map(parse_assignment).collect()
+}
+
 pub fn process(input: String, test_overlap: Overlap) -> Option<u32> {
     input
         .lines()
         .map(|line: &str| -> Option<u32> {
-            let (left, right) = line.split_once(',')?;
-
-            let range_a = parse_range(left)?;
-            let range_b = parse_range(right)?;
+            let assignment = parse_assignment(line)?;
 
-            if overlapping(&range_a, &range_b) >= Some(test_overlap) {
+            if assignment.overlap() >= Some(test_overlap) {
                 Some(1)
             } else {
                 Some(0)
@@ -49,6 +294,49 @@ pub fn process(input: String, test_overlap: Overlap) -> Option<u32> {
         .sum()
 }
 
+pub fn line_overlaps(input: &str) -> Option<Vec<LineOverlap>> {
+    Some(
+        parse_assignments(input)?
+            .iter()
+            .enumerate()
+            .map(|(index, assignment)| {
+                let common = assignment.common();
+                let range = common.ranges().next().cloned();
+
+                LineOverlap {
+                    line: index + 1,
+                    kind: assignment.overlap(),
+                    range,
+                    length: common.covered_len(),
+                    max_simultaneous: assignment.max_simultaneous_overlap(),
+                }
+            })
+            .collect(),
+    )
+}
+
+pub fn coverage(input: &str) -> Option<Coverage> {
+    let mut covered = IntervalSet::new();
+    let mut overlapped = IntervalSet::new();
+
+    for assignment in parse_assignments(input)? {
+        for range in assignment.ranges {
+            let single = IntervalSet::from(range);
+
+            overlapped = overlapped.union(&covered.intersection(&single));
+            covered = covered.union(&single);
+        }
+    }
+
+    Some(Coverage {
+        covered_len: covered.covered_len(),
+        overlapped_len: overlapped.covered_len(),
+        gaps: covered.gaps(),
+        covered,
+        overlapped,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -64,4 +352,82 @@ mod tests {
     fn test_process_partially() {
         assert_eq!(process(MOVES.to_string(), Overlap::Partial), Some(4));
     }
+
+    #[test]
+    fn test_interval_set() {
+        let a = IntervalSet::from_iter([1..=3, 10..=12, 4..=5, 20..=20]);
+        let b = IntervalSet::from_iter([3..=10, 15..=25]);
+
+        assert_eq!(
+            a.ranges().cloned().collect::<Vec<_>>(),
+            vec![1..=5, 10..=12, 20..=20]
+        );
+        assert_eq!(a.covered_len(), 9);
+        assert!(a.contains(&11));
+        assert!(!a.contains(&13));
+        assert_eq!(a.union(&b), IntervalSet::from_iter([1..=12, 15..=25]));
+        assert_eq!(
+            a.intersection(&b),
+            IntervalSet::from_iter([3..=5, 10..=10, 20..=20])
+        );
+        assert_eq!(a.difference(&b), IntervalSet::from_iter([1..=2, 11..=12]));
+        assert_eq!(
+            b.difference(&a),
+            IntervalSet::from_iter([6..=9, 15..=19, 21..=25])
+        );
+        assert_eq!(a.gaps(), IntervalSet::from_iter([6..=9, 13..=19]));
+    }
+
+    #[test]
+    fn test_line_overlaps() {
+        let overlaps = line_overlaps(MOVES).unwrap();
+
+        assert_eq!(
+            overlaps[2],
+            LineOverlap {
+                line: 3,
+                kind: Some(Overlap::Partial),
+                range: Some(7..=7),
+                length: 1,
+                max_simultaneous: 2
+            }
+        );
+        assert_eq!(
+            overlaps[3],
+            LineOverlap {
+                line: 4,
+                kind: Some(Overlap::Fully),
+                range: Some(3..=7),
+                length: 5,
+                max_simultaneous: 2
+            }
+        );
+        assert_eq!(overlaps[0].range, None);
+
+        let stats = coverage(MOVES).unwrap();
+        assert_eq!(stats.covered, IntervalSet::from(2..=9));
+        assert_eq!(stats.overlapped, IntervalSet::from(2..=8));
+        assert_eq!(stats.overlapped_len, 7);
+        assert!(stats.gaps.is_empty());
+    }
+
+    #[test]
+    fn test_n_way_assignments() {
+        let assignments = parse_assignments("2-8,3-5,4-7\n1-4,3-6,4-9\n1-2,2-3,3-4\n5-5").unwrap();
+
+        assert!(assignments[0].one_contains_all());
+        assert_eq!(assignments[0].overlap(), Some(Overlap::Fully));
+        assert_eq!(assignments[0].max_simultaneous_overlap(), 3);
+
+        assert!(!assignments[1].one_contains_all());
+        assert!(assignments[1].pairwise_overlapping());
+        assert_eq!(assignments[1].overlap(), Some(Overlap::Partial));
+        assert_eq!(assignments[1].common(), IntervalSet::from(4..=4));
+
+        assert!(!assignments[2].pairwise_overlapping());
+        assert_eq!(assignments[2].overlap(), None);
+        assert_eq!(assignments[2].max_simultaneous_overlap(), 2);
+
+        assert_eq!(assignments[3].overlap(), Some(Overlap::Fully));
+    }
 }
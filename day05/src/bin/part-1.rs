@@ -6,8 +6,9 @@ fn main() {
     let file = fs::read_to_string("input.txt");
 
     if let Ok(content) = file {
-        let result = process(content, false);
-
-        println!("Top Containers: {}", result.unwrap());
+        match process(content, false) {
+            Ok(result) => println!("Top Containers: {result}"),
+            Err(error) => eprintln!("Error: {error}"),
+        }
     }
 }
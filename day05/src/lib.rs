@@ -1,22 +1,138 @@
 #![feature(iter_array_chunks)]
 
-#[derive(Debug)]
-struct Movement {
-    count: usize,
-    source: usize,
-    target: usize,
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CraneError {
+    MissingCommands,
+    MissingStackNames,
+    MalformedStackLine {
+        line: usize,
+        stack: usize,
+        length: usize,
+    },
+    MalformedCommand {
+        index: usize,
+        line: String,
+    },
+    UnknownStack {
+        index: usize,
+        name: char,
+    },
+    NotEnoughCrates {
+        index: usize,
+        source: char,
+        requested: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for CraneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CraneError::MissingCommands => {
+                write!(f, "expected an empty line between drawing and commands")
+            }
+            CraneError::MissingStackNames => write!(f, "drawing has no line of stack names"),
+            CraneError::MalformedStackLine {
+                line,
+                stack,
+                length,
+            } => write!(
+                f,
+                "line {line} of the drawing has only {length} characters, too short for stack {stack}"
+            ),
+            CraneError::MalformedCommand { index, line } => {
+                write!(f, "command {index} is malformed: {line:?}")
+            }
+            CraneError::UnknownStack { index, name } => {
+                write!(f, "command {index} refers to unknown stack {name}")
+            }
+            CraneError::NotEnoughCrates {
+                index,
+                source,
+                requested,
+                available,
+            } => write!(
+                f,
+                "command {index} moves {requested} crates from stack {source} which holds only {available}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CraneError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Movement {
+    pub count: usize,
+    pub source: usize,
+    pub target: usize,
+}
+
+impl Movement {
+    /// The movement that brings the crates back. This works for both the
+    /// CrateMover 9000 and 9001, moving crates one by one reverses them twice.
+    pub fn inverse(&self) -> Movement {
+        Movement {
+            count: self.count,
+            source: self.target,
+            target: self.source,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stacks {
+    pub names: Vec<char>,
+    pub stacks: Vec<Vec<char>>,
+}
+
+impl Stacks {
+    pub fn tops(&self) -> String {
+        self.stacks.iter().filter_map(|s| s.last()).collect()
+    }
+
+    /// Draws the stacks in the same format that is parsed by `setup_stacks`.
+    pub fn render(&self) -> String {
+        let height = self.stacks.iter().map(Vec::len).max().unwrap_or(0);
+        let mut lines = (0..height)
+            .rev()
+            .map(|level| {
+                self.stacks
+                    .iter()
+                    .map(|stack| match stack.get(level) {
+                        Some(name) => format!("[{name}]"),
+                        None => "   ".to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+
+        lines.push(
+            self.names
+                .iter()
+                .map(|name| format!(" {name} "))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+
+        lines.join("\n")
+    }
 }
 
-fn setup_stacks(desc: &str) -> Option<(Vec<char>, Vec<Vec<char>>)> {
+fn setup_stacks(desc: &str) -> Result<Stacks, CraneError> {
+    let line_count = desc.lines().count();
     let mut lines = desc.lines().rev();
-    let last_line = lines.next()?;
+    let last_line = lines.next().ok_or(CraneError::MissingStackNames)?;
 
-    let stack_names = last_line.split_whitespace().map(|c|c.chars().next()).collect::<Option<Vec<char>>>()?;
+    let stack_names = last_line.split_whitespace().map(|c|c.chars().next()).collect::<Option<Vec<char>>>().ok_or(CraneError::MissingStackNames)?;
     let number_of_stacks = stack_names.len();
 
     let mut stacks = vec![Vec::new(); number_of_stacks];
 
-    for line in lines {
+    for (l, line) in lines.enumerate() {
 
         for (c, stack) in stacks.iter_mut().enumerate().take(number_of_stacks) {
             let container_name = line.chars().nth(c*4+1);
@@ -24,67 +140,416 @@ fn setup_stacks(desc: &str) -> Option<(Vec<char>, Vec<Vec<char>>)> {
                 Some(' ') => {},
                 Some(name) => stack.push(name),
                 None => {
-                    println!("{:?}, {}", line.chars().count(), c);
-                    return None;
+                    return Err(CraneError::MalformedStackLine {
+                        line: line_count - 1 - l,
+                        stack: c + 1,
+                        length: line.chars().count(),
+                    });
                 },
             }
         }
     }
 
 
-    Some((stack_names, stacks))
+    Ok(Stacks {
+        names: stack_names,
+        stacks,
+    })
 }
 
-fn setup_commands(stack_names: &[char], desc: &str) -> Option<Vec<Movement>> {
-    desc.lines().map(|line| {
-        let parts = line.split_whitespace().array_chunks::<6>().next()?;
-        let source_name = parts[3].chars().next()?;
-        let target_name = parts[5].chars().next()?;
-        let source_index = stack_names.iter().position(|&x| x == source_name)?;
-        let target_index = stack_names.iter().position(|&x| x == target_name)?;
+fn setup_commands(stack_names: &[char], desc: &str) -> Result<Vec<Movement>, CraneError> {
+    desc.lines().enumerate().map(|(index, line)| {
+        let malformed = || CraneError::MalformedCommand {
+            index,
+            line: line.to_string(),
+        };
+        let parts = line.split_whitespace().array_chunks::<6>().next().ok_or_else(malformed)?;
+        let source_name = parts[3].chars().next().ok_or_else(malformed)?;
+        let target_name = parts[5].chars().next().ok_or_else(malformed)?;
+        let source_index = stack_names.iter().position(|&x| x == source_name).ok_or(CraneError::UnknownStack { index, name: source_name })?;
+        let target_index = stack_names.iter().position(|&x| x == target_name).ok_or(CraneError::UnknownStack { index, name: target_name })?;
         
-        Some(Movement {
-            count: str::parse(parts[1]).ok()?,
+        Ok(Movement {
+            count: str::parse(parts[1]).map_err(|_| malformed())?,
             source: source_index,
             target: target_index,
         })
     }).collect()
 }
 
-fn apply_commands(stacks: &mut [Vec<char>], commands: Vec<Movement>) {
-    for cmd in commands {    
-        for _ in 0..cmd.count {
-            let e = stacks[cmd.source].pop().unwrap();
-            stacks[cmd.target].push(e)
+/// Checks that every movement only lifts crates that are actually there.
+/// Both crane models move the same number of crates, so the stack heights
+/// are all that needs to be tracked.
+pub fn validate_commands(stacks: &Stacks, commands: &[Movement]) -> Result<(), CraneError> {
+    let mut heights = stacks.stacks.iter().map(Vec::len).collect::<Vec<_>>();
+
+    for (index, cmd) in commands.iter().enumerate() {
+        let available = heights[cmd.source];
+        if cmd.count > available {
+            return Err(CraneError::NotEnoughCrates {
+                index,
+                source: stacks.names[cmd.source],
+                requested: cmd.count,
+                available,
+            });
         }
+        heights[cmd.source] -= cmd.count;
+        heights[cmd.target] += cmd.count;
     }
+
+    Ok(())
 }
 
-fn apply_commands_multiple(stacks: &mut [Vec<char>], commands: Vec<Movement>) {
+fn apply_command(stacks: &mut [Vec<char>], cmd: &Movement) {
+    for _ in 0..cmd.count {
+        let e = stacks[cmd.source].pop().unwrap();
+        stacks[cmd.target].push(e)
+    }
+}
+
+fn apply_command_multiple(stacks: &mut [Vec<char>], cmd: &Movement) {
     let mut crane = Vec::new();
-    for cmd in commands {
-        for _ in 0..cmd.count {
-            let e = stacks[cmd.source].pop().unwrap();
-            crane.push(e)
+    for _ in 0..cmd.count {
+        let e = stacks[cmd.source].pop().unwrap();
+        crane.push(e)
+    }
+    while let Some(c) = crane.pop() {
+        stacks[cmd.target].push(c);
+    }
+}
+
+/// A crane model that decides how the crates of a movement get from the source
+/// to the target stack. The returned work is counted in the crane's own units,
+/// for the basic models that is the number of lifts.
+pub trait Crane: fmt::Debug {
+    fn name(&self) -> String;
+
+    fn apply(&self, stacks: &mut [Vec<char>], cmd: &Movement) -> u64;
+}
+
+/// Moves one crate at a time.
+#[derive(Debug, Clone, Copy)]
+pub struct CrateMover9000;
+
+/// Moves all crates of a movement at once, keeping their order.
+#[derive(Debug, Clone, Copy)]
+pub struct CrateMover9001;
+
+/// Lifts at most `capacity` crates at once, keeping the order inside each batch.
+#[derive(Debug, Clone, Copy)]
+pub struct BoundedCrane {
+    pub capacity: usize,
+}
+
+/// Lifts all crates at once and rotates the batch by `rotation` positions
+/// before setting it down.
+#[derive(Debug, Clone, Copy)]
+pub struct RotatingCrane {
+    pub rotation: usize,
+}
+
+/// Moves like the CrateMover 9001 but charges `lift_cost` per movement plus
+/// `crate_cost` for every crate and every stack it is carried along.
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedCrane {
+    pub lift_cost: u64,
+    pub crate_cost: u64,
+}
+
+impl Crane for CrateMover9000 {
+    fn name(&self) -> String {
+        "CrateMover 9000".to_string()
+    }
+
+    fn apply(&self, stacks: &mut [Vec<char>], cmd: &Movement) -> u64 {
+        apply_command(stacks, cmd);
+        cmd.count as u64
+    }
+}
+
+impl Crane for CrateMover9001 {
+    fn name(&self) -> String {
+        "CrateMover 9001".to_string()
+    }
+
+    fn apply(&self, stacks: &mut [Vec<char>], cmd: &Movement) -> u64 {
+        apply_command_multiple(stacks, cmd);
+        1
+    }
+}
+
+impl Crane for BoundedCrane {
+    fn name(&self) -> String {
+        format!("Bounded crane (capacity {})", self.capacity)
+    }
+
+    fn apply(&self, stacks: &mut [Vec<char>], cmd: &Movement) -> u64 {
+        let capacity = self.capacity.max(1);
+        let mut rest = cmd.count;
+        let mut lifts = 0;
+
+        while rest > 0 {
+            let batch = rest.min(capacity);
+            apply_command_multiple(
+                stacks,
+                &Movement {
+                    count: batch,
+                    ..*cmd
+                },
+            );
+            rest -= batch;
+            lifts += 1;
         }
-        while let Some(c) = crane.pop() {
-            stacks[cmd.target].push(c);
+
+        lifts
+    }
+}
+
+impl Crane for RotatingCrane {
+    fn name(&self) -> String {
+        format!("Rotating crane (by {})", self.rotation)
+    }
+
+    fn apply(&self, stacks: &mut [Vec<char>], cmd: &Movement) -> u64 {
+        let source = &mut stacks[cmd.source];
+        let mut batch = source.split_off(source.len() - cmd.count);
+        if !batch.is_empty() {
+            let rotation = self.rotation % batch.len();
+            batch.rotate_left(rotation);
         }
+        stacks[cmd.target].append(&mut batch);
+        1
     }
 }
 
-pub fn process(input: String, move_multiple: bool) -> Option<String> {
-    let (stack_description, command_description) = input.split_once("\n\n")?;
-    let (stack_names, mut stacks) = setup_stacks(stack_description)?;
-    let commands = setup_commands(&stack_names, command_description)?;
+impl Crane for WeightedCrane {
+    fn name(&self) -> String {
+        format!(
+            "Weighted crane (lift {}, crate {})",
+            self.lift_cost, self.crate_cost
+        )
+    }
 
+    fn apply(&self, stacks: &mut [Vec<char>], cmd: &Movement) -> u64 {
+        apply_command_multiple(stacks, cmd);
+        let distance = cmd.source.abs_diff(cmd.target) as u64;
+        self.lift_cost + self.crate_cost * cmd.count as u64 * distance
+    }
+}
+
+/// Selects a crane model by name: `9000`, `9001`, `bounded:<capacity>`,
+/// `rotating:<rotation>` or `weighted:<lift cost>:<crate cost>`.
+pub fn crane_model(spec: &str) -> Option<Box<dyn Crane>> {
+    let mut parts = spec.split(':');
+    let model: Box<dyn Crane> = match parts.next()? {
+        "9000" => Box::new(CrateMover9000),
+        "9001" => Box::new(CrateMover9001),
+        "bounded" => Box::new(BoundedCrane {
+            capacity: parts.next()?.parse().ok()?,
+        }),
+        "rotating" => Box::new(RotatingCrane {
+            rotation: parts.next()?.parse().ok()?,
+        }),
+        "weighted" => Box::new(WeightedCrane {
+            lift_cost: parts.next()?.parse().ok()?,
+            crate_cost: parts.next()?.parse().ok()?,
+        }),
+        _ => return None,
+    };
+
+    if parts.next().is_some() {
+        None
+    } else {
+        Some(model)
+    }
+}
+
+fn parse(input: &str) -> Result<(Stacks, Vec<Movement>), CraneError> {
+    let (stack_description, command_description) = input
+        .split_once("\n\n")
+        .ok_or(CraneError::MissingCommands)?;
+    let stacks = setup_stacks(stack_description)?;
+    let commands = setup_commands(&stacks.names, command_description)?;
+
+    Ok((stacks, commands))
+}
+
+fn setup(input: &str) -> Result<(Stacks, Vec<Movement>), CraneError> {
+    let (stacks, commands) = parse(input)?;
+    validate_commands(&stacks, &commands)?;
+
+    Ok((stacks, commands))
+}
+
+fn crate_mover(move_multiple: bool) -> &'static dyn Crane {
     if move_multiple {
-        apply_commands_multiple(&mut stacks, commands);
+        &CrateMover9001
     } else {
-        apply_commands(&mut stacks, commands);
+        &CrateMover9000
+    }
+}
+
+#[derive(Debug)]
+pub struct Simulator {
+    initial: Stacks,
+    commands: Vec<Movement>,
+    crane: Box<dyn Crane>,
+}
+
+impl Simulator {
+    pub fn new(input: &str, move_multiple: bool) -> Result<Self, CraneError> {
+        if move_multiple {
+            Self::with_crane(input, Box::new(CrateMover9001))
+        } else {
+            Self::with_crane(input, Box::new(CrateMover9000))
+        }
+    }
+
+    pub fn with_crane(input: &str, crane: Box<dyn Crane>) -> Result<Self, CraneError> {
+        let (initial, commands) = setup(input)?;
+
+        Ok(Simulator {
+            initial,
+            commands,
+            crane,
+        })
+    }
+
+    pub fn initial(&self) -> &Stacks {
+        &self.initial
+    }
+
+    pub fn commands(&self) -> &[Movement] {
+        &self.commands
+    }
+
+    fn step(&self, stacks: &mut Stacks, cmd: &Movement) -> u64 {
+        self.crane.apply(&mut stacks.stacks, cmd)
+    }
+
+    /// Yields every movement together with the stacks right after it was applied.
+    pub fn trace(&self) -> impl Iterator<Item = (Movement, Stacks)> + '_ {
+        self.commands
+            .iter()
+            .scan(self.initial.clone(), |stacks, cmd| {
+                self.step(stacks, cmd);
+                Some((*cmd, stacks.clone()))
+            })
+    }
+
+    /// The stacks after the first `step` movements, `replay(0)` being the initial state.
+    pub fn replay(&self, step: usize) -> Option<Stacks> {
+        let mut stacks = self.initial.clone();
+        for cmd in self.commands.get(..step)? {
+            self.step(&mut stacks, cmd);
+        }
+        Some(stacks)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CraneReport {
+    pub crane: String,
+    pub top: String,
+    pub work: u64,
+}
+
+/// Runs the same input with every given crane and reports the resulting
+/// top crates together with the total work spent.
+pub fn crane_report(
+    input: &str,
+    cranes: &[Box<dyn Crane>],
+) -> Result<Vec<CraneReport>, CraneError> {
+    let (initial, commands) = setup(input)?;
+
+    Ok(cranes
+        .iter()
+        .map(|crane| {
+            let mut stacks = initial.clone();
+            let work = commands
+                .iter()
+                .map(|cmd| crane.apply(&mut stacks.stacks, cmd))
+                .sum();
+
+            CraneReport {
+                crane: crane.name(),
+                top: stacks.tops(),
+                work,
+            }
+        })
+        .collect())
+}
+
+pub fn process_with_crane(input: &str, crane: &dyn Crane) -> Result<String, CraneError> {
+    let (mut stacks, commands) = setup(input)?;
+
+    for cmd in &commands {
+        crane.apply(&mut stacks.stacks, cmd);
     }
 
-    Some(stacks.iter().filter_map(|s| s.last()).collect())
+    Ok(stacks.tops())
+}
+
+pub fn process(input: String, move_multiple: bool) -> Result<String, CraneError> {
+    process_with_crane(&input, crate_mover(move_multiple))
+}
+
+/// The inverse movements of a crane log, last movement first.
+pub fn inverse_commands(commands: &[Movement]) -> Vec<Movement> {
+    commands.iter().rev().map(Movement::inverse).collect()
+}
+
+/// Reconstructs the initial arrangement from the final stacks by undoing the
+/// commands. Errors refer to the index of the command in the original log.
+pub fn reconstruct(
+    final_stacks: &Stacks,
+    commands: &[Movement],
+    move_multiple: bool,
+) -> Result<Stacks, CraneError> {
+    let inverse = inverse_commands(commands);
+    validate_commands(final_stacks, &inverse).map_err(|e| match e {
+        CraneError::NotEnoughCrates {
+            index,
+            source,
+            requested,
+            available,
+        } => CraneError::NotEnoughCrates {
+            index: commands.len() - 1 - index,
+            source,
+            requested,
+            available,
+        },
+        e => e,
+    })?;
+
+    let crane = crate_mover(move_multiple);
+    let mut stacks = final_stacks.clone();
+    for cmd in &inverse {
+        crane.apply(&mut stacks.stacks, cmd);
+    }
+
+    Ok(stacks)
+}
+
+/// Expects the final drawing followed by the crane log, in the puzzle's format.
+pub fn undo(input: &str, move_multiple: bool) -> Result<Stacks, CraneError> {
+    let (final_stacks, commands) = parse(input)?;
+
+    reconstruct(&final_stacks, &commands, move_multiple)
+}
+
+/// Applies the commands and their inverses and checks that the result matches
+/// the stacks drawn in the input.
+pub fn round_trip(input: &str, move_multiple: bool) -> Result<bool, CraneError> {
+    let (initial, commands) = setup(input)?;
+    let mut stacks = initial.clone();
+
+    let crane = crate_mover(move_multiple);
+    for cmd in &commands {
+        crane.apply(&mut stacks.stacks, cmd);
+    }
+
+    Ok(reconstruct(&stacks, &commands, move_multiple)? == initial)
 }
 
 #[cfg(test)]
@@ -95,11 +560,114 @@ mod tests {
 
     #[test]
     fn test_process() {
-        assert_eq!(process(MOVES.to_string(), false), Some("CMZ".to_string()));
+        assert_eq!(process(MOVES.to_string(), false), Ok("CMZ".to_string()));
     }
 
     #[test]
     fn test_process_multiple() {
-        assert_eq!(process(MOVES.to_string(), true), Some("MCD".to_string()));
+        assert_eq!(process(MOVES.to_string(), true), Ok("MCD".to_string()));
+    }
+
+    #[test]
+    fn test_simulator() {
+        let simulator = Simulator::new(MOVES, false).unwrap();
+        let (drawing, _) = MOVES.split_once("\n\n").unwrap();
+
+        assert_eq!(simulator.initial().render(), drawing);
+        assert_eq!(simulator.replay(0).as_ref(), Some(simulator.initial()));
+        assert_eq!(simulator.replay(5), None);
+
+        let trace = simulator.trace().collect::<Vec<_>>();
+        assert_eq!(trace.len(), 4);
+        assert_eq!(
+            trace[1].0,
+            Movement {
+                count: 3,
+                source: 0,
+                target: 2
+            }
+        );
+        assert_eq!(
+            trace[1].1.render(),
+            "        [Z]\n        [N]\n    [C] [D]\n    [M] [P]\n 1   2   3 "
+        );
+        assert_eq!(simulator.replay(2), Some(trace[1].1.clone()));
+        assert_eq!(trace[3].1.tops(), "CMZ");
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let input = MOVES.replace("move 2 from 2 to 1", "move 4 from 2 to 1");
+        assert_eq!(
+            process(input, true),
+            Err(CraneError::NotEnoughCrates {
+                index: 2,
+                source: '2',
+                requested: 4,
+                available: 2
+            })
+        );
+
+        let input = MOVES.replace("from 1 to 3", "from 1 to 4");
+        assert_eq!(
+            process(input, false),
+            Err(CraneError::UnknownStack {
+                index: 1,
+                name: '4'
+            })
+        );
+
+        let input = MOVES.replace("[N] [C]    ", "[N] [C]");
+        assert_eq!(
+            process(input, false),
+            Err(CraneError::MalformedStackLine {
+                line: 2,
+                stack: 3,
+                length: 7
+            })
+        );
+    }
+
+    #[test]
+    fn test_crane_models() {
+        let cranes = ["9000", "9001", "bounded:2", "rotating:1", "weighted:2:1"]
+            .iter()
+            .map(|spec| crane_model(spec).unwrap())
+            .collect::<Vec<_>>();
+        let report = crane_report(MOVES, &cranes).unwrap();
+
+        assert_eq!(
+            report
+                .iter()
+                .map(|r| (r.top.as_str(), r.work))
+                .collect::<Vec<_>>(),
+            vec![("CMZ", 7), ("MCD", 4), ("MCZ", 5), ("CMZ", 4), ("MCD", 18)]
+        );
+        assert!(crane_model("bounded").is_none());
+        assert!(crane_model("9002").is_none());
+    }
+
+    #[test]
+    fn test_undo() {
+        assert_eq!(round_trip(MOVES, false), Ok(true));
+        assert_eq!(round_trip(MOVES, true), Ok(true));
+        assert_eq!(round_trip(&MOVES.replace(" 3 \n", " 3\n"), false), Ok(true));
+
+        let (drawing, commands) = MOVES.split_once("\n\n").unwrap();
+        let simulator = Simulator::new(MOVES, true).unwrap();
+        let final_drawing = simulator.replay(4).unwrap().render();
+        let restored = undo(&format!("{final_drawing}\n\n{commands}"), true).unwrap();
+        assert_eq!(restored.render(), drawing);
+
+        let broken = format!("{final_drawing}\n\nmove 1 from 1 to 2\nmove 3 from 3 to 2");
+        assert_eq!(
+            undo(&broken, true),
+            Err(CraneError::NotEnoughCrates {
+                index: 1,
+                source: '2',
+                requested: 3,
+                available: 1
+            })
+        );
     }
 }
@@ -1,11 +1,270 @@
 #![feature(iter_array_chunks)]
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::io::{self, BufReader, Bytes, Read};
 
+/// Tracks the last `streak` bytes of a stream together with how often each
+/// byte value occurs among them, so every step is O(1) regardless of `streak`.
+#[derive(Debug, Clone)]
+pub struct MarkerDetector {
+    streak: usize,
+    window: Vec<u8>,
+    counts: [usize; 256],
+    distinct: usize,
+    position: usize,
+}
+
+impl MarkerDetector {
+    pub fn new(streak: usize) -> Self {
+        MarkerDetector {
+            streak,
+            window: vec![0; streak],
+            counts: [0; 256],
+            distinct: 0,
+            position: 0,
+        }
+    }
+
+    /// Number of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Consumes the next byte and returns whether the last `streak` bytes are
+    /// all different from each other.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.streak == 0 {
+            self.position += 1;
+            return true;
+        }
+
+        let slot = self.position % self.streak;
+        if self.position >= self.streak {
+            let old = self.window[slot] as usize;
+            self.counts[old] -= 1;
+            if self.counts[old] == 0 {
+                self.distinct -= 1;
+            }
+        }
+
+        self.window[slot] = byte;
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 1 {
+            self.distinct += 1;
+        }
+        self.position += 1;
+
+        self.distinct == self.streak
+    }
+}
+
+/// Iterator over the end positions of all markers in a byte stream.
+pub struct Markers<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    detector: MarkerDetector,
+}
+
+impl<R: Read> Iterator for Markers<R> {
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for byte in self.bytes.by_ref() {
+            match byte {
+                Ok(b) => {
+                    if self.detector.push(b) {
+                        return Some(Ok(self.detector.position()));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// Reads the source incrementally and yields the position right after every
+/// window of `streak` distinct bytes.
+pub fn markers<R: Read>(reader: R, streak: usize) -> Markers<R> {
+    Markers {
+        bytes: BufReader::new(reader).bytes(),
+        detector: MarkerDetector::new(streak),
+    }
+}
+
+/// Marker lengths of the framing protocol. Every frame starts with a
+/// start-of-packet marker and a start-of-message marker, both searched the
+/// same way `process` does, so the message marker may overlap the packet
+/// marker. The payload follows the message marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protocol {
+    pub packet_marker: usize,
+    pub message_marker: usize,
+    /// Length of the distinct-byte window that ends a payload and starts the
+    /// search for the next frame. Short terminators end payloads early, with
+    /// `None` the payload runs until the end of the stream.
+    pub payload_terminator: Option<usize>,
+}
+
+impl Default for Protocol {
+    /// The puzzle's protocol, a single frame spanning the whole stream.
+    fn default() -> Self {
+        Protocol {
+            packet_marker: 4,
+            message_marker: 14,
+            payload_terminator: None,
+        }
+    }
+}
+
+/// A decoded frame, all offsets are byte positions in the stream.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Frame {
+    pub packet_offset: usize,
+    pub message_offset: usize,
+    pub payload_offset: usize,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn end(&self) -> usize {
+        self.payload_offset + self.payload.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Packet,
+    Message,
+    Payload,
+}
+
+/// Iterator over the frames of a byte stream, see `decode`.
+pub struct Frames<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    protocol: Protocol,
+    stage: Stage,
+    // the detectors see every byte, markers are only accepted if their window
+    // starts at or after the current frame's start
+    packet: MarkerDetector,
+    message: MarkerDetector,
+    terminator: Option<MarkerDetector>,
+    offset: usize,
+    frame_start: usize,
+    frame: Frame,
+}
+
+impl<R: Read> Frames<R> {
+    /// Moves through the stages the current byte completes, returns the frame
+    /// it terminated, if any.
+    fn advance(&mut self, packet: bool, message: bool, terminator: bool) -> Option<Frame> {
+        let mut finished = None;
+
+        loop {
+            match self.stage {
+                Stage::Packet
+                    if packet && self.offset - self.protocol.packet_marker >= self.frame_start =>
+                {
+                    self.frame.packet_offset = self.offset - self.protocol.packet_marker;
+                    self.stage = Stage::Message;
+                }
+                Stage::Message
+                    if message
+                        && self.offset - self.protocol.message_marker >= self.frame_start =>
+                {
+                    self.frame.message_offset = self.offset - self.protocol.message_marker;
+                    self.frame.payload_offset = self.offset;
+                    self.frame.payload.clear();
+                    self.stage = Stage::Payload;
+                    return finished;
+                }
+                Stage::Payload if terminator => {
+                    let length = self.protocol.payload_terminator.unwrap_or(0);
+                    let end = self.offset - length;
+                    if end < self.frame.payload_offset {
+                        return finished;
+                    }
+
+                    self.frame.payload.truncate(end - self.frame.payload_offset);
+                    finished = Some(std::mem::take(&mut self.frame));
+                    self.frame_start = end;
+                    self.stage = Stage::Packet;
+                }
+                _ => return finished,
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Frames<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(byte) = self.bytes.next() {
+            let b = match byte {
+                Ok(b) => b,
+                Err(e) => return Some(Err(e)),
+            };
+            self.offset += 1;
+
+            if self.stage == Stage::Payload {
+                self.frame.payload.push(b);
+            }
+
+            let packet = self.packet.push(b);
+            let message = self.message.push(b);
+            let terminator = self.terminator.as_mut().is_some_and(|t| t.push(b));
+
+            if let Some(frame) = self.advance(packet, message, terminator) {
+                return Some(Ok(frame));
+            }
+        }
+
+        if self.stage == Stage::Payload {
+            self.stage = Stage::Packet;
+            return Some(Ok(std::mem::take(&mut self.frame)));
+        }
+
+        None
+    }
+}
+
+/// Splits the stream into frames. A start-of-packet marker without a
+/// following start-of-message marker does not produce a frame.
+pub fn decode<R: Read>(reader: R, protocol: Protocol) -> Frames<R> {
+    Frames {
+        bytes: BufReader::new(reader).bytes(),
+        protocol,
+        stage: Stage::Packet,
+        packet: MarkerDetector::new(protocol.packet_marker),
+        message: MarkerDetector::new(protocol.message_marker),
+        terminator: protocol.payload_terminator.map(MarkerDetector::new),
+        offset: 0,
+        frame_start: 0,
+        frame: Frame::default(),
+    }
+}
+
+/// Position in characters right after the first window of `streak` distinct
+/// characters. Only ASCII input can take the byte-wise detector.
 pub fn process(input: String, streak: usize) -> Option<usize> {
-    let inter = input.chars().collect::<Vec<char>>();
-    for (i, chars) in inter.windows(streak).enumerate() {
-        if HashSet::<&char>::from_iter(chars).len() == streak {
-            return Some(i+streak)
+    if input.is_ascii() {
+        return markers(input.as_bytes(), streak).next()?.ok();
+    }
+
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut counts = HashMap::<char, usize>::new();
+    for (i, c) in chars.iter().enumerate() {
+        *counts.entry(*c).or_default() += 1;
+        if i >= streak {
+            let old = chars[i - streak];
+            if let Some(count) = counts.get_mut(&old) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&old);
+                }
+            }
+        }
+        if i + 1 >= streak && counts.len() == streak {
+            return Some(i + 1);
         }
     }
     None
@@ -32,4 +291,69 @@ mod tests {
         assert_eq!(process("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg".to_string(), 14), Some(29));
         assert_eq!(process("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw".to_string(), 14), Some(26));
     }
+
+    #[test]
+    fn test_all_markers() {
+        let positions = markers("aabcaadefg".as_bytes(), 3)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(positions, vec![4, 5, 8, 9, 10]);
+        assert!(markers("aaaa".as_bytes(), 2).next().is_none());
+        assert_eq!(process("ééab".to_string(), 2), Some(3));
+        assert_eq!(process("ééaéa".to_string(), 3), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        let protocol = Protocol {
+            packet_marker: 2,
+            message_marker: 3,
+            payload_terminator: Some(2),
+        };
+        let frames = decode("aabbbcddddeefgggg".as_bytes(), protocol)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(
+            frames,
+            vec![
+                Frame {
+                    packet_offset: 1,
+                    message_offset: 4,
+                    payload_offset: 7,
+                    payload: b"dd".to_vec(),
+                },
+                Frame {
+                    packet_offset: 9,
+                    message_offset: 11,
+                    payload_offset: 14,
+                    payload: b"ggg".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(frames[1].end(), 17);
+        assert_eq!(decode("aabbbcc".as_bytes(), protocol).count(), 0);
+    }
+
+    #[test]
+    fn test_decode_puzzle() {
+        for input in [
+            "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+            "bvwbjplbgvbhsrlpgdmjqwftvncz",
+            "nppdvjthqldpwncqszvftbrmjlhg",
+            "nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg",
+            "zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw",
+        ] {
+            let mut frames = decode(input.as_bytes(), Protocol::default());
+            let frame = frames.next().unwrap().unwrap();
+            let packet = process(input.to_string(), 4).unwrap();
+            let message = process(input.to_string(), 14).unwrap();
+
+            assert_eq!(frame.packet_offset + 4, packet);
+            assert_eq!(frame.message_offset + 14, message);
+            assert_eq!(frame.payload_offset, message);
+            assert_eq!(frame.payload, &input.as_bytes()[message..]);
+            assert!(frames.next().is_none());
+        }
+    }
 }
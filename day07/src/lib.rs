@@ -1,13 +1,17 @@
 #![feature(iter_array_chunks)]
-#![feature(iter_intersperse)]
 
 use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
 
 use nom::branch::alt;
 use nom::bytes::complete::is_not;
 use nom::bytes::complete::tag;
 use nom::character::complete::line_ending;
+use nom::multi::many0;
 use nom::multi::separated_list1;
+use nom::sequence::preceded;
 use nom::sequence::separated_pair;
 use nom::*;
 
@@ -26,7 +30,7 @@ pub enum Cd<'a> {
 
 #[derive(Debug)]
 pub enum File<'a> {
-    File { size: u32, name: &'a str },
+    File { size: u64, name: &'a str },
     Dir { name: &'a str },
 }
 
@@ -36,7 +40,7 @@ fn file_name(input: &str) -> IResult<&str, &str> {
 
 fn file(input: &str) -> IResult<&str, File> {
     let (input, (size, name)) =
-        separated_pair(character::complete::u32, tag(" "), file_name)(input)?;
+        separated_pair(character::complete::u64, tag(" "), file_name)(input)?;
 
     Ok((input, File::File { size, name }))
 }
@@ -50,9 +54,8 @@ fn directory(input: &str) -> IResult<&str, File> {
 
 fn ls(input: &str) -> IResult<&str, Operation> {
     let (input, _) = tag("$ ls")(input)?;
-    let (input, _) = line_ending(input)?;
     let (input, files): (&str, Vec<File>) =
-        separated_list1(line_ending, alt((file, directory)))(input)?;
+        many0(preceded(line_ending, alt((file, directory))))(input)?;
 
     Ok((input, Operation::Ls(files)))
 }
@@ -76,48 +79,248 @@ fn commands(input: &str) -> IResult<&str, Vec<Operation>> {
     Ok((input, cmds))
 }
 
-fn collect_folder_sizes(operations: Vec<Operation>) -> Vec<(String, u32)> {
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Directory {
+    pub files: BTreeMap<String, u64>,
+    pub directories: BTreeMap<String, Directory>,
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent == "/" {
+        format!("/{name}")
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+fn human_size(size: u64) -> String {
+    let units = ["K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = None;
+
+    for u in units {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = Some(u);
+    }
+
+    match unit {
+        None => size.to_string(),
+        Some(u) if value < 10.0 => format!("{value:.1}{u}"),
+        Some(u) => format!("{value:.0}{u}"),
+    }
+}
+
+impl Directory {
+    fn get_mut(&mut self, path: &[&str]) -> &mut Directory {
+        path.iter().fold(self, |dir, name| {
+            dir.directories.entry(name.to_string()).or_default()
+        })
+    }
+
+    /// Total size of all files inside this directory and its subdirectories.
+    pub fn size(&self) -> u64 {
+        self.files.values().sum::<u64>()
+            + self.directories.values().map(Directory::size).sum::<u64>()
+    }
+
+    /// Number of nested directory levels, a directory without subdirectories has depth 0.
+    pub fn depth(&self) -> usize {
+        self.directories
+            .values()
+            .map(|d| d.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn walk<'a>(&'a self, path: &str, visit: &mut impl FnMut(&str, &'a Directory)) {
+        visit(path, self);
+        for (name, dir) in &self.directories {
+            dir.walk(&join_path(path, name), visit);
+        }
+    }
+
+    /// All directories with their absolute path and total size, the root being `/`.
+    pub fn directory_sizes(&self) -> Vec<(String, u64)> {
+        let mut sizes = Vec::new();
+        self.walk("/", &mut |path, dir| {
+            sizes.push((path.to_string(), dir.size()))
+        });
+        sizes
+    }
+
+    pub fn largest_directories(&self, n: usize) -> Vec<(String, u64)> {
+        let mut sizes = self.directory_sizes();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sizes.truncate(n);
+        sizes
+    }
+
+    pub fn files_larger_than(&self, threshold: u64) -> Vec<(String, u64)> {
+        let mut files = Vec::new();
+        self.walk("/", &mut |path, dir| {
+            for (name, size) in &dir.files {
+                if *size > threshold {
+                    files.push((join_path(path, name), *size));
+                }
+            }
+        });
+        files
+    }
+
+    /// Subdirectories and files sorted by name, files with their size.
+    fn entries(&self) -> Vec<(&String, Option<u64>)> {
+        let mut entries = self
+            .directories
+            .keys()
+            .map(|n| (n, None))
+            .chain(self.files.iter().map(|(n, s)| (n, Some(*s))))
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    fn render_into(&self, name: &str, indent: usize, out: &mut Vec<String>) {
+        out.push(format!("{}- {name} (dir)", "  ".repeat(indent)));
+
+        for (entry, size) in self.entries() {
+            match size {
+                Some(size) => out.push(format!(
+                    "{}- {entry} (file, size={size})",
+                    "  ".repeat(indent + 1)
+                )),
+                None => self.directories[entry].render_into(entry, indent + 1, out),
+            }
+        }
+    }
+
+    /// Renders the tree in the same style as the puzzle description.
+    pub fn render_tree(&self) -> String {
+        let mut lines = Vec::new();
+        self.render_into("/", 0, &mut lines);
+        lines.join("\n")
+    }
+
+    /// One line per directory with its human readable size, largest first, like `du -h | sort -hr`.
+    pub fn du(&self) -> String {
+        self.largest_directories(usize::MAX)
+            .iter()
+            .map(|(path, size)| format!("{}\t{path}", human_size(*size)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A file was listed again with a different size, the latest size is kept.
+    FileSizeChanged { path: String, old: u64, new: u64 },
+    /// `cd` into a directory that no listing has mentioned before.
+    UnknownDirectory { path: String },
+    /// `cd ..` while already in the root directory.
+    AboveRoot,
+    /// The transcript could not be parsed from this line on.
+    UnparsedInput { line: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    pub tree: Directory,
+    pub warnings: Vec<Warning>,
+}
+
+fn stack_path(path_stack: &[&str]) -> String {
+    path_stack
+        .iter()
+        .fold("/".to_string(), |p, n| join_path(&p, n))
+}
+
+fn build_tree(operations: Vec<Operation>) -> Replay {
     let mut path_stack = Vec::<&str>::new();
-    let mut directory_flat_sizes = BTreeMap::<String, u32>::new();
+    let mut root = Directory::default();
+    let mut warnings = Vec::new();
 
     for cmd in operations {
         match cmd {
             Operation::Cd(Cd::Root) => path_stack.clear(),
             Operation::Cd(Cd::Up) => {
-                path_stack.pop();
+                if path_stack.pop().is_none() {
+                    warnings.push(Warning::AboveRoot);
+                }
+            }
+            Operation::Cd(Cd::Down(name)) => {
+                if !root.get_mut(&path_stack).directories.contains_key(name) {
+                    warnings.push(Warning::UnknownDirectory {
+                        path: join_path(&stack_path(&path_stack), name),
+                    });
+                }
+                path_stack.push(name);
             }
-            Operation::Cd(Cd::Down(name)) => path_stack.push(name),
             Operation::Ls(files) => {
+                let dir = root.get_mut(&path_stack);
                 for file in files {
-                    if let File::File { size, .. } = file {
-                        for p in 0..=path_stack.len() {
-                            let path = path_stack
-                                .iter()
-                                .take(p)
-                                .cloned()
-                                .intersperse("/")
-                                .collect::<String>();
-                            let old = directory_flat_sizes.entry(path).or_insert(0);
-                            *old += size;
+                    match file {
+                        File::File { size, name } => {
+                            // listing a directory twice must not count its files twice
+                            match dir.files.insert(name.to_string(), size) {
+                                Some(old) if old != size => {
+                                    warnings.push(Warning::FileSizeChanged {
+                                        path: join_path(&stack_path(&path_stack), name),
+                                        old,
+                                        new: size,
+                                    })
+                                }
+                                _ => {}
+                            }
+                        }
+                        File::Dir { name } => {
+                            dir.directories.entry(name.to_string()).or_default();
                         }
                     }
                 }
             }
         }
     }
-    let mut sorted = directory_flat_sizes
-        .clone()
-        .into_iter()
-        .collect::<Vec<(String, u32)>>();
+
+    Replay {
+        tree: root,
+        warnings,
+    }
+}
+
+/// Replays the transcript and reports everything that looks inconsistent.
+pub fn replay(input: &str) -> Option<Replay> {
+    let (rest, operations) = commands(input).ok()?;
+    let mut result = build_tree(operations);
+
+    let unparsed = rest.trim_start();
+    if !unparsed.is_empty() {
+        let offset = input.len() - unparsed.len();
+        result.warnings.push(Warning::UnparsedInput {
+            line: input[..offset].matches('\n').count() + 1,
+        });
+    }
+
+    Some(result)
+}
+
+pub fn parse_tree(input: &str) -> Option<Directory> {
+    Some(replay(input)?.tree)
+}
+
+fn collect_folder_sizes(tree: &Directory) -> Vec<(String, u64)> {
+    let mut sorted = tree.directory_sizes();
     sorted.sort_by_key(|(_, size)| *size);
 
     sorted
 }
 
-pub fn process_sum(input: String, threshold: u32) -> Option<u32> {
-    let operations = commands(&input).ok()?.1;
+pub fn process_sum(input: String, threshold: u64) -> Option<u64> {
+    let tree = parse_tree(&input)?;
 
-    let sorted_sized = collect_folder_sizes(operations);
+    let sorted_sized = collect_folder_sizes(&tree);
 
     Some(
         sorted_sized
@@ -129,11 +332,11 @@ pub fn process_sum(input: String, threshold: u32) -> Option<u32> {
     )
 }
 
-pub fn process_deletion(input: String, total_space: u32, needed_space: u32) -> Option<u32> {
-    let operations = commands(&input).ok()?.1;
+pub fn process_deletion(input: String, total_space: u64, needed_space: u64) -> Option<u64> {
+    let tree = parse_tree(&input)?;
 
-    let sorted_sized = collect_folder_sizes(operations);
-    let (_, total_size) = sorted_sized.last()?;
+    let sorted_sized = collect_folder_sizes(&tree);
+    let total_size = tree.size();
     let free_space = total_space - total_size;
     let to_delete = needed_space - free_space;
 
@@ -142,6 +345,316 @@ pub fn process_deletion(input: String, total_space: u32, needed_space: u32) -> O
     Some(size_to_delete)
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct DeletionConstraints {
+    /// Whether single files may be deleted in addition to whole directories.
+    pub allow_files: bool,
+    /// Paths that must survive, together with everything inside them.
+    pub protected: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletionPlan {
+    pub paths: Vec<String>,
+    pub deleted: u64,
+    /// Whether the search finished, rather than running out of budget.
+    pub optimal: bool,
+}
+
+/// Number of search steps `plan_deletion` may take before settling.
+pub const SEARCH_BUDGET: usize = 1 << 20;
+
+struct Candidate {
+    path: String,
+    size: u64,
+    /// Position of the first candidate after this one's subtree.
+    end: usize,
+    deletable: bool,
+}
+
+fn is_within(path: &str, dir: &str) -> bool {
+    dir == "/" || path == dir || path.starts_with(&format!("{dir}/"))
+}
+
+impl Directory {
+    fn collect_candidates(
+        &self,
+        path: &str,
+        constraints: &DeletionConstraints,
+        out: &mut Vec<Candidate>,
+    ) {
+        let deletable = |p: &str| {
+            !constraints
+                .protected
+                .iter()
+                .any(|protected| is_within(p, protected) || is_within(protected, p))
+        };
+
+        for (name, dir) in &self.directories {
+            let dir_path = join_path(path, name);
+            let index = out.len();
+            out.push(Candidate {
+                deletable: deletable(&dir_path),
+                path: dir_path.clone(),
+                size: dir.size(),
+                end: 0,
+            });
+            dir.collect_candidates(&dir_path, constraints, out);
+            out[index].end = out.len();
+        }
+
+        if constraints.allow_files {
+            for (name, size) in &self.files {
+                let file_path = join_path(path, name);
+                out.push(Candidate {
+                    deletable: deletable(&file_path),
+                    path: file_path,
+                    size: *size,
+                    end: out.len() + 1,
+                });
+            }
+        }
+    }
+
+    /// Finds the non-nested directories (and files, if allowed) with the smallest
+    /// total size that is at least `to_free`.
+    ///
+    /// Candidates are laid out in pre-order so that choosing one skips its whole
+    /// subtree. The search is a depth-first branch and bound that only keeps the
+    /// current path, so memory stays linear in the number of candidates. It gives
+    /// up after `SEARCH_BUDGET` steps and returns the best plan found so far with
+    /// `optimal` unset.
+    pub fn plan_deletion(
+        &self,
+        to_free: u64,
+        constraints: &DeletionConstraints,
+    ) -> Option<DeletionPlan> {
+        if to_free == 0 {
+            return Some(DeletionPlan {
+                paths: Vec::new(),
+                deleted: 0,
+                optimal: true,
+            });
+        }
+
+        let mut candidates = Vec::new();
+        self.collect_candidates("/", constraints, &mut candidates);
+
+        // the most that can still be deleted from each position onwards
+        let mut reach = vec![0u64; candidates.len() + 1];
+        for (position, candidate) in candidates.iter().enumerate().rev() {
+            reach[position] = reach[position + 1];
+            if candidate.deletable {
+                let taken = candidate.size.saturating_add(reach[candidate.end]);
+                reach[position] = reach[position].max(taken);
+            }
+        }
+        if reach[0] < to_free {
+            return None;
+        }
+
+        let mut best: Option<(u64, Vec<usize>)> = None;
+        let mut chosen: Vec<usize> = Vec::new();
+        // (position, sum, length of the chosen path, candidate taken on the way here)
+        let mut stack = vec![(0, 0u64, 0, None)];
+        let mut steps = 0;
+
+        while let Some((position, sum, depth, taken)) = stack.pop() {
+            chosen.truncate(depth);
+            chosen.extend(taken);
+
+            if sum >= to_free {
+                if best.as_ref().is_none_or(|(b, _)| sum < *b) {
+                    best = Some((sum, chosen.clone()));
+                }
+                if sum == to_free {
+                    break;
+                }
+                continue;
+            }
+            steps += 1;
+            if steps > SEARCH_BUDGET {
+                let (deleted, chosen) = best?;
+                return Some(to_plan(&candidates, deleted, &chosen, false));
+            }
+
+            let Some(candidate) = candidates.get(position) else {
+                continue;
+            };
+            if sum.saturating_add(reach[position]) < to_free {
+                continue;
+            }
+
+            stack.push((position + 1, sum, chosen.len(), None));
+            let with = sum.saturating_add(candidate.size);
+            if candidate.deletable
+                && candidate.size > 0
+                && best.as_ref().is_none_or(|(b, _)| with < *b)
+            {
+                stack.push((candidate.end, with, chosen.len(), Some(position)));
+            }
+        }
+
+        let (deleted, chosen) = best?;
+        Some(to_plan(&candidates, deleted, &chosen, true))
+    }
+}
+
+fn to_plan(
+    candidates: &[Candidate],
+    deleted: u64,
+    chosen: &[usize],
+    optimal: bool,
+) -> DeletionPlan {
+    let mut paths = chosen
+        .iter()
+        .map(|&i| candidates[i].path.clone())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    DeletionPlan {
+        paths,
+        deleted,
+        optimal,
+    }
+}
+
+impl Directory {
+    /// Creates the tree below `path`. Files only get their length set, which
+    /// leaves them sparse on filesystems that support it. Nothing is written if
+    /// any name could point outside of `path`.
+    pub fn materialize(&self, path: &Path) -> io::Result<()> {
+        self.check_names()?;
+        self.write_to(path)
+    }
+
+    fn check_names(&self) -> io::Result<()> {
+        for name in self.files.keys().chain(self.directories.keys()) {
+            if name.is_empty()
+                || name == "."
+                || name == ".."
+                || name.contains('/')
+                || Path::new(name).is_absolute()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid entry name: {:?}", name),
+                ));
+            }
+        }
+        self.directories
+            .values()
+            .try_for_each(Directory::check_names)
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)?;
+        for (name, dir) in &self.directories {
+            dir.write_to(&path.join(name))?;
+        }
+        for (name, size) in &self.files {
+            fs::File::create(path.join(name))?.set_len(*size)?;
+        }
+
+        Ok(())
+    }
+
+    fn append_to_tar<W: Write>(
+        &self,
+        builder: &mut tar::Builder<W>,
+        prefix: &Path,
+    ) -> io::Result<()> {
+        for (name, dir) in &self.directories {
+            let path = prefix.join(name);
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            builder.append_data(&mut header, &path, io::empty())?;
+            dir.append_to_tar(builder, &path)?;
+        }
+        for (name, size) in &self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_size(*size);
+            builder.append_data(&mut header, prefix.join(name), io::repeat(0).take(*size))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the tree as a tar stream whose files are filled with zeros. Names
+    /// are checked the same way as for `materialize`.
+    pub fn write_tar<W: Write>(&self, writer: W) -> io::Result<W> {
+        self.check_names()?;
+        let mut builder = tar::Builder::new(writer);
+        self.append_to_tar(&mut builder, Path::new(""))?;
+        builder.into_inner()
+    }
+
+    /// Reads an existing directory tree, symlinks and other special files are skipped.
+    pub fn from_disk(path: &Path) -> io::Result<Directory> {
+        let mut dir = Directory::default();
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                dir.directories
+                    .insert(name, Directory::from_disk(&entry.path())?);
+            } else if file_type.is_file() {
+                dir.files.insert(name, entry.metadata()?.len());
+            }
+        }
+
+        Ok(dir)
+    }
+
+    fn transcript_into(&self, out: &mut Vec<String>) {
+        out.push("$ ls".to_string());
+
+        for (name, size) in self.entries() {
+            match size {
+                Some(size) => out.push(format!("{size} {name}")),
+                None => out.push(format!("dir {name}")),
+            }
+        }
+
+        for (name, dir) in &self.directories {
+            out.push(format!("$ cd {name}"));
+            dir.transcript_into(out);
+            out.push("$ cd ..".to_string());
+        }
+    }
+
+    /// A `$ cd`/`$ ls` transcript that lists every directory exactly once.
+    /// Names containing whitespace can not be represented in that format.
+    pub fn transcript(&self) -> String {
+        let mut lines = vec!["$ cd /".to_string()];
+        self.transcript_into(&mut lines);
+        lines.join("\n")
+    }
+}
+
+pub fn transcript_from_disk(path: &Path) -> io::Result<String> {
+    Ok(Directory::from_disk(path)?.transcript())
+}
+
+pub fn process_deletion_plan(
+    input: String,
+    total_space: u64,
+    needed_space: u64,
+    constraints: &DeletionConstraints,
+) -> Option<DeletionPlan> {
+    let tree = parse_tree(&input)?;
+    let free_space = total_space.saturating_sub(tree.size());
+
+    tree.plan_deletion(needed_space.saturating_sub(free_space), constraints)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,4 +669,231 @@ mod tests {
             Some(24933642)
         );
     }
+
+    #[test]
+    fn test_tree() {
+        let tree = parse_tree(include_str!("test.txt")).unwrap();
+
+        assert_eq!(
+            tree.render_tree(),
+            "- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)"
+        );
+        assert_eq!(tree.depth(), 2);
+        assert_eq!(
+            tree.largest_directories(2),
+            vec![("/".to_string(), 48381165), ("/d".to_string(), 24933642)]
+        );
+        assert_eq!(
+            tree.files_larger_than(8000000),
+            vec![
+                ("/b.txt".to_string(), 14848514),
+                ("/c.dat".to_string(), 8504156),
+                ("/d/d.log".to_string(), 8033020)
+            ]
+        );
+        assert_eq!(tree.du(), "46M\t/\n24M\t/d\n93K\t/a\n584\t/a/e");
+    }
+
+    #[test]
+    fn test_replay_warnings() {
+        let input = "$ cd /
+$ ls
+dir a
+100 b
+$ ls
+dir a
+100 b
+$ cd a
+$ ls
+$ cd ..
+$ cd ..
+$ cd c
+$ ls
+5000000000 d
+$ ls
+7 d
+$ cd /
+$ ls
+120 b
+$ rm b";
+        let result = replay(input).unwrap();
+
+        assert_eq!(result.tree.size(), 127);
+        assert_eq!(result.tree.directories["a"], Directory::default());
+        assert_eq!(
+            result.warnings,
+            vec![
+                Warning::AboveRoot,
+                Warning::UnknownDirectory {
+                    path: "/c".to_string()
+                },
+                Warning::FileSizeChanged {
+                    path: "/c/d".to_string(),
+                    old: 5000000000,
+                    new: 7
+                },
+                Warning::FileSizeChanged {
+                    path: "/b".to_string(),
+                    old: 100,
+                    new: 120
+                },
+                Warning::UnparsedInput { line: 20 },
+            ]
+        );
+        assert_eq!(replay(include_str!("test.txt")).unwrap().warnings, vec![]);
+    }
+
+    #[test]
+    fn test_deletion_plan() {
+        const COMMANDS: &str = include_str!("test.txt");
+
+        let directories = DeletionConstraints::default();
+        assert_eq!(
+            process_deletion_plan(COMMANDS.to_string(), 70000000, 30000000, &directories),
+            Some(DeletionPlan {
+                paths: vec!["/d".to_string()],
+                deleted: 24933642,
+                optimal: true,
+            })
+        );
+
+        let files = DeletionConstraints {
+            allow_files: true,
+            protected: vec!["/c.dat".to_string()],
+        };
+        assert_eq!(
+            process_deletion_plan(COMMANDS.to_string(), 70000000, 30000000, &files),
+            Some(DeletionPlan {
+                paths: vec!["/d/d.ext".to_string(), "/d/j".to_string()],
+                deleted: 9686326,
+                optimal: true,
+            })
+        );
+
+        let protected = DeletionConstraints {
+            allow_files: false,
+            protected: vec!["/d/k".to_string()],
+        };
+        assert_eq!(
+            process_deletion_plan(COMMANDS.to_string(), 70000000, 30000000, &protected),
+            None
+        );
+    }
+
+    #[test]
+    fn test_deletion_plan_large() {
+        const COMMANDS: &str = "$ cd /\n$ ls\ndir a\ndir b\n60000000000 c\n$ cd a\n$ ls\n30000000000 x\n45000000000 y\n$ cd ..\n$ cd b\n$ ls\n25000000000 z";
+        let tree = parse_tree(COMMANDS).unwrap();
+
+        let directories = DeletionConstraints::default();
+        assert_eq!(
+            tree.plan_deletion(100000000000, &directories),
+            Some(DeletionPlan {
+                paths: vec!["/a".to_string(), "/b".to_string()],
+                deleted: 100000000000,
+                optimal: true,
+            })
+        );
+
+        let files = DeletionConstraints {
+            allow_files: true,
+            protected: Vec::new(),
+        };
+        assert_eq!(
+            tree.plan_deletion(101000000000, &files),
+            Some(DeletionPlan {
+                paths: vec!["/a/y".to_string(), "/c".to_string()],
+                deleted: 105000000000,
+                optimal: true,
+            })
+        );
+        assert_eq!(tree.plan_deletion(200000000000, &files), None);
+    }
+
+    #[test]
+    fn test_deletion_plan_inexact() {
+        let mut commands = String::from("$ cd /\n$ ls\n");
+        for i in 1..=60u64 {
+            commands.push_str(&format!("{} f{i}\n", 2 * (1000003 + 7919 * i)));
+        }
+        let tree = parse_tree(&commands).unwrap();
+        let files = DeletionConstraints {
+            allow_files: true,
+            protected: Vec::new(),
+        };
+
+        // all sizes are even, so an odd target can never be hit exactly
+        let plan = tree.plan_deletion(35000001, &files).unwrap();
+        assert!(plan.deleted > 35000001);
+        assert_eq!(plan.deleted % 2, 0);
+        assert!(!plan.optimal);
+
+        let small = parse_tree("$ cd /\n$ ls\n4 a\n6 b\n11 c").unwrap();
+        assert_eq!(
+            small.plan_deletion(9, &files),
+            Some(DeletionPlan {
+                paths: vec!["/a".to_string(), "/b".to_string()],
+                deleted: 10,
+                optimal: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let tree = parse_tree(include_str!("test.txt")).unwrap();
+        assert_eq!(parse_tree(&tree.transcript()), Some(tree.clone()));
+
+        let path = std::env::temp_dir().join(format!("day07-export-{}", std::process::id()));
+        tree.materialize(&path).unwrap();
+        let transcript = transcript_from_disk(&path);
+        let from_disk = Directory::from_disk(&path);
+        fs::remove_dir_all(&path).unwrap();
+        assert_eq!(from_disk.unwrap(), tree);
+        assert_eq!(transcript.unwrap(), tree.transcript());
+
+        for hostile in ["5 ../escaped.txt", "7 /tmp/absolute.txt", "dir ..", "3 a/b"] {
+            let tree = parse_tree(&format!("$ cd /\n$ ls\n{}", hostile)).unwrap();
+            let path = std::env::temp_dir().join(format!("day07-hostile-{}", std::process::id()));
+            let error = tree.materialize(&path.join("root")).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(!path.exists());
+
+            let error = tree.write_tar(Vec::new()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let small = parse_tree("$ cd /\n$ ls\ndir a\n3 b\n$ cd a\n$ ls\n2 c").unwrap();
+        let archive = small.write_tar(Vec::new()).unwrap();
+        let mut entries = tar::Archive::new(archive.as_slice())
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                (e.path().unwrap().display().to_string(), e.size())
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                ("a".to_string(), 0),
+                ("a/c".to_string(), 2),
+                ("b".to_string(), 3)
+            ]
+        );
+    }
 }
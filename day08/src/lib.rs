@@ -1,49 +1,305 @@
 #![feature(iter_array_chunks)]
 #![feature(iter_intersperse)]
 
-use take_until::TakeUntilExt;
-
-fn walk_tree_cast<I:Iterator<Item=u32>+Clone>(height: u32, ray: I) -> (usize, bool)  {
-    (
-        ray.clone().take_until(|h| h >= &height).count(),
-        ray.skip_while(|h| h < &height).count() == 0
-    )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forest {
+    pub width: usize,
+    pub height: usize,
+    heights: Vec<u32>,
 }
 
-pub fn process(input: String) -> Option<(u32, usize)> {
-    let grid = input.lines()
-    .map(str::chars)
-    .map(|r| r.map(|c| c as u32 - 48).collect()).collect::<Vec<Vec<u32>>>();
-    
-    let height = grid.len();
-    let width = grid.get(0)?.len();
-
-    let mut count = 0;
-    let mut highest_score = 0;
-
-    for (r, row) in grid.iter().enumerate() {
-        for (c, &own_height) in row.iter().enumerate() {
-            let rays = [
-                walk_tree_cast(own_height, row[0..c].iter().rev().cloned()),
-                walk_tree_cast(own_height, row[(c+1)..width].iter().cloned()),
-                walk_tree_cast(own_height, grid[0..r].iter().rev().map(|h| h[c])),
-                walk_tree_cast(own_height, grid[(r+1)..height].iter().map(|h| h[c])),
-            ];
-
-            let is_visible = rays.iter().any(|r| r.1);
-            let scenic_score = rays.iter().map(|r| r.0).product();
-
-            if is_visible {
-                count += 1;
+impl Forest {
+    /// Rows of single digits, or rows of whitespace separated numbers for taller trees.
+    /// All rows must have the same length.
+    pub fn parse(input: &str) -> Option<Forest> {
+        let rows = input
+            .lines()
+            .map(|line| {
+                if line.contains(char::is_whitespace) {
+                    line.split_whitespace().map(|h| h.parse().ok()).collect()
+                } else {
+                    line.chars().map(|c| c.to_digit(10)).collect()
+                }
+            })
+            .collect::<Option<Vec<Vec<u32>>>>()?;
+
+        let width = rows.first()?.len();
+        if rows.iter().any(|r| r.len() != width) {
+            return None;
+        }
+
+        Some(Forest {
+            width,
+            height: rows.len(),
+            heights: rows.concat(),
+        })
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> Option<u32> {
+        if column < self.width {
+            self.heights.get(row * self.width + column).copied()
+        } else {
+            None
+        }
+    }
+
+    fn get_signed(&self, (row, column): (isize, isize)) -> Option<u32> {
+        self.get(usize::try_from(row).ok()?, usize::try_from(column).ok()?)
+    }
+
+    /// Whether every tree strictly between `from` and `to` is lower than `eye_height`,
+    /// following a Bresenham line. Cells outside of the forest do not block the view.
+    fn line_of_sight(&self, from: (isize, isize), to: (isize, isize), eye_height: u32) -> bool {
+        bresenham(from, to)
+            .skip(1)
+            .take_while(|cell| *cell != to)
+            .all(|cell| self.get_signed(cell).is_none_or(|h| h < eye_height))
+    }
+
+    /// Whether tree `a` can see tree `b` along a straight line of any slope. Like
+    /// along the axes a tree can look over all trees that are lower than itself,
+    /// including the first one that blocks its view.
+    pub fn can_see(&self, a: (usize, usize), b: (usize, usize)) -> Option<bool> {
+        let height = self.get(a.0, a.1)?;
+        self.get(b.0, b.1)?;
+
+        Some(self.line_of_sight(
+            (a.0 as isize, a.1 as isize),
+            (b.0 as isize, b.1 as isize),
+            height,
+        ))
+    }
+
+    /// All trees an observer at the given row and column can see when looking
+    /// from `eye_height`. The observer may also stand outside of the forest,
+    /// a tree at the observer's own position is not included.
+    pub fn visible_from(&self, observer: (isize, isize), eye_height: u32) -> Vec<(usize, usize)> {
+        (0..self.height)
+            .flat_map(|r| (0..self.width).map(move |c| (r, c)))
+            .filter(|&(r, c)| {
+                let cell = (r as isize, c as isize);
+                cell != observer && self.line_of_sight(observer, cell, eye_height)
+            })
+            .collect()
+    }
+
+    /// Walks a line of cells from its first to its last cell and records for
+    /// every cell how far it can see back towards the first cell and whether it
+    /// is visible from outside at that end. Cells that are lower than the
+    /// current one can never block any later view, so they get dropped from the
+    /// stack and every cell is pushed and popped at most once.
+    fn scan(&self, cells: impl Iterator<Item = usize>, side: usize, analysis: &mut Analysis) {
+        let mut stack: Vec<(usize, u32)> = Vec::new();
+
+        for (i, cell) in cells.enumerate() {
+            let height = self.heights[cell];
+            while stack.last().is_some_and(|(_, h)| *h < height) {
+                stack.pop();
             }
 
-            if scenic_score > highest_score {
-                highest_score = scenic_score;
+            match stack.last() {
+                Some((j, _)) => analysis.distances[cell][side] = i - j,
+                None => {
+                    analysis.distances[cell][side] = i;
+                    analysis.visible[cell] = true;
+                }
             }
+
+            stack.push((i, height));
         }
     }
 
-    Some((count, highest_score))
+    /// Computes visibility and viewing distances of all trees in O(width * height).
+    pub fn analyze(&self) -> Analysis {
+        let mut analysis = Analysis {
+            width: self.width,
+            height: self.height,
+            visible: vec![false; self.heights.len()],
+            distances: vec![[0; 4]; self.heights.len()],
+        };
+
+        for r in 0..self.height {
+            let row = (0..self.width).map(|c| r * self.width + c);
+            self.scan(row.clone(), LEFT, &mut analysis);
+            self.scan(row.rev(), RIGHT, &mut analysis);
+        }
+        for c in 0..self.width {
+            let column = (0..self.height).map(|r| r * self.width + c);
+            self.scan(column.clone(), UP, &mut analysis);
+            self.scan(column.rev(), DOWN, &mut analysis);
+        }
+
+        analysis
+    }
+}
+
+/// The grid cells on the line between both cells, including both ends.
+fn bresenham(from: (isize, isize), to: (isize, isize)) -> impl Iterator<Item = (isize, isize)> {
+    let (dr, dc) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (sr, sc) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let mut error = dr + dc;
+    let mut current = Some(from);
+
+    std::iter::from_fn(move || {
+        let cell = current?;
+        current = if cell == to {
+            None
+        } else {
+            let (mut r, mut c) = cell;
+            let e2 = 2 * error;
+            if e2 >= dc {
+                error += dc;
+                r += sr;
+            }
+            if e2 <= dr {
+                error += dr;
+                c += sc;
+            }
+            Some((r, c))
+        };
+        Some(cell)
+    })
+}
+
+const UP: usize = 0;
+const LEFT: usize = 1;
+const DOWN: usize = 2;
+const RIGHT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewingDistances {
+    pub up: usize,
+    pub left: usize,
+    pub down: usize,
+    pub right: usize,
+}
+
+impl ViewingDistances {
+    pub fn scenic_score(&self) -> usize {
+        self.up * self.left * self.down * self.right
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    pub width: usize,
+    pub height: usize,
+    visible: Vec<bool>,
+    /// Viewing distances up, left, down and right.
+    distances: Vec<[usize; 4]>,
+}
+
+/// Maps `value` from `0..=max` onto `0..=maxval`, rounding to the nearest step.
+fn scale(value: usize, max: usize, maxval: usize) -> usize {
+    let (value, max, maxval) = (value as u128, max as u128, maxval as u128);
+    ((value * maxval + max / 2) / max) as usize
+}
+
+impl Analysis {
+    pub fn visible_count(&self) -> usize {
+        self.visible.iter().filter(|v| **v).count()
+    }
+
+    pub fn scenic_score(&self, row: usize, column: usize) -> Option<usize> {
+        if column < self.width {
+            let distances = self.distances.get(row * self.width + column)?;
+            Some(distances.iter().product())
+        } else {
+            None
+        }
+    }
+
+    pub fn highest_score(&self) -> usize {
+        self.distances
+            .iter()
+            .map(|d| d.iter().product())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn grid<T>(&self, cell: impl Fn(usize) -> T) -> Vec<Vec<T>> {
+        (0..self.height)
+            .map(|r| (0..self.width).map(|c| cell(r * self.width + c)).collect())
+            .collect()
+    }
+
+    /// Whether each tree is visible from outside the forest, row by row.
+    pub fn visibility_mask(&self) -> Vec<Vec<bool>> {
+        self.grid(|i| self.visible[i])
+    }
+
+    pub fn viewing_distances(&self) -> Vec<Vec<ViewingDistances>> {
+        self.grid(|i| {
+            let d = self.distances[i];
+            ViewingDistances {
+                up: d[UP],
+                left: d[LEFT],
+                down: d[DOWN],
+                right: d[RIGHT],
+            }
+        })
+    }
+
+    pub fn score_grid(&self) -> Vec<Vec<usize>> {
+        self.grid(|i| self.distances[i].iter().product())
+    }
+
+    /// Row and column of the tree with the highest scenic score, the first one in reading order on ties.
+    pub fn best_tree(&self) -> Option<(usize, usize)> {
+        let best = self
+            .distances
+            .iter()
+            .map(|d| d.iter().product::<usize>())
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, score)| *score)?
+            .0;
+
+        Some((best / self.width, best % self.width))
+    }
+
+    /// The score grid as plain PGM image, scores are scaled down if they exceed
+    /// the 16 bit range of the format.
+    pub fn to_pgm(&self) -> String {
+        let max = self.highest_score().max(1);
+        let maxval = max.min(u16::MAX as usize);
+        let rows = self
+            .score_grid()
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|score| scale(*score, max, maxval).to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "P2\n{} {}\n{maxval}\n{}\n",
+            self.width,
+            self.height,
+            rows.join("\n")
+        )
+    }
+
+    pub fn to_csv(&self) -> String {
+        self.score_grid()
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|score| score.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub fn process(input: String) -> Option<(u32, usize)> {
+    let analysis = Forest::parse(&input)?.analyze();
+
+    Some((analysis.visible_count() as u32, analysis.highest_score()))
 }
 
 #[cfg(test)]
@@ -58,4 +314,71 @@ mod tests {
         assert_eq!(process(COMMANDS.to_string()), Some((21, 8)));
     }
 
+    #[test]
+    fn test_tall_and_wide_forest() {
+        let forest = Forest::parse("1 1 1 1\n1 12 10 1\n1 1 11 1").unwrap();
+        let analysis = forest.analyze();
+
+        assert_eq!((forest.width, forest.height), (4, 3));
+        assert_eq!(forest.get(1, 1), Some(12));
+        assert_eq!(analysis.visible_count(), 12);
+        assert_eq!(analysis.scenic_score(1, 1), Some(2));
+        assert_eq!(analysis.scenic_score(0, 4), None);
+        assert_eq!(analysis.scenic_score(3, 0), None);
+        assert_eq!(analysis.highest_score(), 2);
+        assert_eq!(Forest::parse("123\n12"), None);
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let forest = Forest::parse(COMMANDS).unwrap();
+
+        assert_eq!(
+            bresenham((0, 0), (4, 2)).collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+        );
+        assert_eq!(forest.can_see((1, 1), (3, 3)), Some(true));
+        assert_eq!(forest.can_see((0, 0), (2, 2)), Some(false));
+        assert_eq!(forest.can_see((0, 0), (4, 2)), Some(false));
+        assert_eq!(forest.can_see((4, 2), (0, 0)), Some(false));
+        assert_eq!(forest.can_see((2, 0), (0, 2)), Some(true));
+        assert_eq!(forest.can_see((0, 0), (5, 5)), None);
+
+        let small = Forest::parse("111\n191\n111").unwrap();
+        assert_eq!(small.visible_from((1, 1), 9).len(), 8);
+        let from_outside = small.visible_from((-1, 1), 5);
+        assert_eq!(from_outside.len(), 8);
+        assert!(!from_outside.contains(&(2, 1)));
+    }
+
+    #[test]
+    fn test_score_maps() {
+        let analysis = Forest::parse(COMMANDS).unwrap().analyze();
+
+        assert_eq!(analysis.best_tree(), Some((3, 2)));
+        assert_eq!(
+            analysis.viewing_distances()[3][2],
+            ViewingDistances {
+                up: 2,
+                left: 2,
+                down: 1,
+                right: 2
+            }
+        );
+        assert_eq!(
+            analysis.visibility_mask()[1],
+            vec![true, true, true, false, true]
+        );
+        assert_eq!(analysis.score_grid()[1], vec![0, 1, 4, 1, 0]);
+        assert_eq!(analysis.to_csv().lines().nth(3), Some("0,1,8,3,0"));
+        assert!(analysis
+            .to_pgm()
+            .starts_with("P2\n5 5\n8\n0 0 0 0 0\n0 1 4 1 0\n"));
+
+        let huge = usize::MAX / 3;
+        assert_eq!(scale(huge, huge, 65535), 65535);
+        assert_eq!(scale(huge / 2, huge, 65535), 32767);
+        assert_eq!(scale(1, huge, 65535), 0);
+    }
+
 }
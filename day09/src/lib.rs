@@ -2,7 +2,7 @@
 #![feature(iter_intersperse)]
 #![feature(array_windows)]
 
-use std::collections::BTreeSet;
+use std::collections::HashMap;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag;
@@ -16,6 +16,10 @@ enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -25,9 +29,9 @@ struct Movement {
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
-struct Position {
-    x: i32,
-    y: i32,
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
 }
 
 #[derive(Debug, Clone)]
@@ -36,25 +40,109 @@ struct Rope {
     segments: Vec<Position>,
 }
 
+/// A knot moving `count` times in the same direction, `start` being the first position reached.
+#[derive(Debug, Clone, Copy)]
+struct Stretch {
+    start: Position,
+    direction: Direction,
+    count: u32,
+}
+
+/// Visited positions as a bitmap of 8x8 tiles, only tiles that were touched are allocated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VisitedSet {
+    tiles: HashMap<(i32, i32), u64>,
+    len: usize,
+}
+
 impl Movement {
-    fn to_steps(self) -> Vec<Direction> {
-        vec![self.direction; self.distance as usize]
+    fn to_steps(self) -> impl Iterator<Item = Direction> {
+        std::iter::repeat_n(self.direction, self.distance as usize)
+    }
+}
+
+impl Stretch {
+    fn positions(self) -> impl Iterator<Item = Position> {
+        (0..self.count as i32).map(move |i| Position {
+            x: self.start.x + i * self.direction.dx(),
+            y: self.start.y + i * self.direction.dy(),
+        })
+    }
+}
+
+impl VisitedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn locate(p: Position) -> ((i32, i32), u64) {
+        ((p.x >> 3, p.y >> 3), 1 << (((p.y & 7) << 3) | (p.x & 7)))
+    }
+
+    pub fn insert(&mut self, p: Position) -> bool {
+        let (tile, bit) = Self::locate(p);
+        let bits = self.tiles.entry(tile).or_default();
+        let fresh = *bits & bit == 0;
+        *bits |= bit;
+        self.len += fresh as usize;
+        fresh
+    }
+
+    pub fn contains(&self, p: Position) -> bool {
+        let (tile, bit) = Self::locate(p);
+        self.tiles.get(&tile).is_some_and(|bits| bits & bit != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The visited positions in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = Position> + '_ {
+        self.tiles.iter().flat_map(|(&(tx, ty), &bits)| {
+            (0..64)
+                .filter(move |i| bits & (1 << i) != 0)
+                .map(move |i| Position {
+                    x: (tx << 3) | (i & 7),
+                    y: (ty << 3) | (i >> 3),
+                })
+        })
+    }
+}
+
+impl Extend<Position> for VisitedSet {
+    fn extend<I: IntoIterator<Item = Position>>(&mut self, iter: I) {
+        for p in iter {
+            self.insert(p);
+        }
+    }
+}
+
+impl FromIterator<Position> for VisitedSet {
+    fn from_iter<I: IntoIterator<Item = Position>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
     }
 }
 
 impl Direction {
     fn dx(&self) -> i32 {
         match self {
-            Self::Left => 1,
-            Self::Right => -1,
+            Self::Left | Self::UpLeft | Self::DownLeft => -1,
+            Self::Right | Self::UpRight | Self::DownRight => 1,
             _ => 0,
         }
     }
 
     fn dy(&self) -> i32 {
         match self {
-            Self::Up => -1,
-            Self::Down => 1,
+            Self::Up | Self::UpLeft | Self::UpRight => -1,
+            Self::Down | Self::DownLeft | Self::DownRight => 1,
             _ => 0,
         }
     }
@@ -72,35 +160,86 @@ impl Position {
         }
     }
 
-    fn follow(self, head: Position) -> Option<Position> {
-        let delta_x = head.x - self.x;
-        let delta_y = head.y - self.y;
-        let (x_abs, x_sgn) = (delta_x.abs(), delta_x.signum());
-        let (y_abs, y_sgn) = (delta_y.abs(), delta_y.signum());
-        let chebyshev_distance = i32::max(x_abs, y_abs);
+    /// Moves the knot towards the knot ahead until the rule is satisfied again
+    /// or its steps stop making progress.
+    fn follow(self, head: Position, rule: &dyn FollowRule) -> Position {
+        let mut knot = self;
+        while !rule.is_close_enough(knot, head) {
+            let next = rule.step(knot, head);
+            if next == knot {
+                break;
+            }
+            knot = next;
+        }
+        knot
+    }
+}
+
+/// Decides how close a knot has to stay to the knot ahead of it and how it
+/// catches up once it is too far away. Rules may only depend on the relative
+/// position of the two knots.
+pub trait FollowRule {
+    fn is_close_enough(&self, knot: Position, ahead: Position) -> bool;
+
+    /// A single step of the knot towards the knot ahead, diagonal by default.
+    fn step(&self, knot: Position, ahead: Position) -> Position {
+        Position {
+            x: knot.x + (ahead.x - knot.x).signum(),
+            y: knot.y + (ahead.y - knot.y).signum(),
+        }
+    }
+}
+
+/// The puzzle's rule: knots have to touch, diagonally touching included.
+#[derive(Debug, Clone, Copy)]
+pub struct ChebyshevAdjacent;
+
+/// Knots have to touch orthogonally and only move orthogonally.
+#[derive(Debug, Clone, Copy)]
+pub struct ManhattanAdjacent;
+
+/// A rope with slack: knots only follow once they are more than
+/// `max_distance` apart in either direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Elastic {
+    pub max_distance: u32,
+}
+
+impl FollowRule for ChebyshevAdjacent {
+    fn is_close_enough(&self, knot: Position, ahead: Position) -> bool {
+        (ahead.x - knot.x).abs() <= 1 && (ahead.y - knot.y).abs() <= 1
+    }
+}
+
+impl FollowRule for ManhattanAdjacent {
+    fn is_close_enough(&self, knot: Position, ahead: Position) -> bool {
+        (ahead.x - knot.x).abs() + (ahead.y - knot.y).abs() <= 1
+    }
+
+    fn step(&self, knot: Position, ahead: Position) -> Position {
+        let (delta_x, delta_y) = (ahead.x - knot.x, ahead.y - knot.y);
 
-        if chebyshev_distance < 2 {
-            Some(self)
+        if delta_x.abs() >= delta_y.abs() {
+            Position {
+                x: knot.x + delta_x.signum(),
+                ..knot
+            }
         } else {
-            match (x_abs, y_abs) {
-                (_, 0) => Some(Position {
-                    x: self.x + x_sgn,
-                    ..self
-                }),
-                (0, _) => Some(Position {
-                    y: self.y + y_sgn,
-                    ..self
-                }),
-                (l, r) if l + r <= 4 => Some(Position {
-                    x: self.x + x_sgn,
-                    y: self.y + y_sgn,
-                }),
-                _ => None,
+            Position {
+                y: knot.y + delta_y.signum(),
+                ..knot
             }
         }
     }
 }
 
+impl FollowRule for Elastic {
+    fn is_close_enough(&self, knot: Position, ahead: Position) -> bool {
+        ahead.x.abs_diff(knot.x) <= self.max_distance
+            && ahead.y.abs_diff(knot.y) <= self.max_distance
+    }
+}
+
 impl Rope {
     fn new(length: usize) -> Self {
         Rope {
@@ -109,20 +248,64 @@ impl Rope {
         }
     }
 
-    fn move_head(&self, dir: Direction) -> Option<Rope> {
-        let new_head = self.head.moved_in_direction(dir);
+    /// Moves the head a single step and reports whether every knot moved
+    /// along with it, i.e. the whole rope was just shifted.
+    fn move_head(&mut self, dir: Direction, rule: &dyn FollowRule) -> bool {
+        self.head = self.head.moved_in_direction(dir);
+
+        let mut ahead = self.head;
+        let mut shifted = true;
+        for knot in self.segments.iter_mut() {
+            let followed = knot.follow(ahead, rule);
+            shifted &= followed == knot.moved_in_direction(dir);
+            *knot = followed;
+            ahead = followed;
+        }
+        shifted
+    }
+
+    /// Executes a whole movement, reporting the positions of every knot by its
+    /// index. Once a step shifts the whole rope every following step does the
+    /// same, so the rest of the movement is skipped in one go.
+    fn travel(
+        &mut self,
+        movement: Movement,
+        rule: &dyn FollowRule,
+        mut visit: impl FnMut(usize, Stretch),
+    ) {
+        let direction = movement.direction;
+
+        for done in 1..=movement.distance {
+            let shifted = self.move_head(direction, rule);
+            let remaining = movement.distance - done;
+
+            for (index, knot) in self.knots().enumerate() {
+                visit(
+                    index,
+                    Stretch {
+                        start: knot,
+                        direction,
+                        count: if shifted { remaining + 1 } else { 1 },
+                    },
+                );
+            }
 
-        Some(Rope {
-            head: new_head,
-            segments: self
-                .segments
-                .iter()
-                .scan(Some(new_head), |a, b| {
-                    *a = b.follow((*a)?);
-                    Some(*a)
-                })
-                .collect::<Option<Vec<_>>>()?,
-        })
+            if shifted {
+                let (dx, dy) = (
+                    direction.dx() * remaining as i32,
+                    direction.dy() * remaining as i32,
+                );
+                for knot in std::iter::once(&mut self.head).chain(self.segments.iter_mut()) {
+                    knot.x += dx;
+                    knot.y += dy;
+                }
+                return;
+            }
+        }
+    }
+
+    fn knots(&self) -> impl Iterator<Item = Position> + '_ {
+        std::iter::once(self.head).chain(self.segments.iter().copied())
     }
 
     fn tail(&self) -> Option<Position> {
@@ -132,6 +315,10 @@ impl Rope {
 
 fn movement(input: &str) -> IResult<&str, Movement> {
     let (input, direction) = alt((
+        tag("UL").map(|_| Direction::UpLeft),
+        tag("UR").map(|_| Direction::UpRight),
+        tag("DL").map(|_| Direction::DownLeft),
+        tag("DR").map(|_| Direction::DownRight),
         tag("U").map(|_| Direction::Up),
         tag("D").map(|_| Direction::Down),
         tag("L").map(|_| Direction::Left),
@@ -155,16 +342,132 @@ fn moves(input: &str) -> IResult<&str, Vec<Movement>> {
     Ok((input, cmds))
 }
 
-pub fn process(input: String, length: usize) -> Option<usize> {
-    let (_, mvs) = moves(&input).ok()?;
+/// The positions visited by every knot, the head first and the tail last.
+pub fn simulate(input: &str, length: usize, rule: &dyn FollowRule) -> Option<Vec<VisitedSet>> {
+    let (_, mvs) = moves(input).ok()?;
+
+    let mut rope = Rope::new(length);
+    let mut visited = vec![VisitedSet::from_iter([Position::new()]); length + 1];
+
+    for mv in mvs {
+        rope.travel(mv, rule, |index, stretch| {
+            visited[index].extend(stretch.positions())
+        });
+    }
+
+    Some(visited)
+}
+
+/// The knot positions before the first step and after every single step,
+/// the head first and the tail last.
+pub fn snapshots(input: &str, length: usize, rule: &dyn FollowRule) -> Option<Vec<Vec<Position>>> {
+    let (_, mvs) = moves(input).ok()?;
 
     let mut rope = Rope::new(length);
-    let mut visited = BTreeSet::<Position>::new();
+    let mut frames = vec![rope.knots().collect::<Vec<_>>()];
 
     for dir in mvs.iter().copied().flat_map(Movement::to_steps) {
-        rope = rope.move_head(dir)?;
+        rope.move_head(dir, rule);
+        frames.push(rope.knots().collect());
+    }
+
+    Some(frames)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl Bounds {
+    pub fn of(positions: impl IntoIterator<Item = Position>) -> Option<Bounds> {
+        positions.into_iter().fold(None, |bounds, p| {
+            let Bounds { min, max } = bounds.unwrap_or(Bounds { min: p, max: p });
+            Some(Bounds {
+                min: Position {
+                    x: min.x.min(p.x),
+                    y: min.y.min(p.y),
+                },
+                max: Position {
+                    x: max.x.max(p.x),
+                    y: max.y.max(p.y),
+                },
+            })
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.x - self.min.x + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.y - self.min.y + 1) as usize
+    }
+
+    fn render(&self, cell: impl Fn(Position) -> char) -> String {
+        (self.min.y..=self.max.y)
+            .map(|y| {
+                (self.min.x..=self.max.x)
+                    .map(|x| cell(Position { x, y }))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The area covered by any knot during the whole simulation.
+pub fn trail_bounds(snapshots: &[Vec<Position>]) -> Option<Bounds> {
+    Bounds::of(snapshots.iter().flatten().copied())
+}
+
+/// Knots past the ninth have no digit of their own and are all drawn as `*`.
+fn knot_label(index: usize, length: usize) -> char {
+    match index {
+        0 => 'H',
+        1 if length == 1 => 'T',
+        i @ 1..=9 => (b'0' + i as u8) as char,
+        _ => '*',
+    }
+}
+
+/// Draws the knots like the puzzle does, knots closer to the head hide the ones behind them.
+pub fn render_knots(knots: &[Position], bounds: &Bounds) -> String {
+    let length = knots.len().saturating_sub(1);
+    bounds.render(|p| {
+        knots
+            .iter()
+            .position(|k| *k == p)
+            .map(|i| knot_label(i, length))
+            .unwrap_or(if p == Position::new() { 's' } else { '.' })
+    })
+}
+
+pub fn render_trail(visited: &VisitedSet, bounds: &Bounds) -> String {
+    bounds.render(|p| {
+        if p == Position::new() {
+            's'
+        } else if visited.contains(p) {
+            '#'
+        } else {
+            '.'
+        }
+    })
+}
 
-        visited.insert(rope.tail()?);
+pub fn process(input: String, length: usize) -> Option<usize> {
+    let (_, mvs) = moves(&input).ok()?;
+
+    let mut rope = Rope::new(length);
+    let mut visited = VisitedSet::new();
+
+    for mv in mvs {
+        rope.travel(mv, &ChebyshevAdjacent, |index, stretch| {
+            if index == length {
+                visited.extend(stretch.positions())
+            }
+        });
     }
 
     Some(visited.len())
@@ -177,16 +480,11 @@ pub fn process_alternative(input: String, length: usize) -> Option<usize> {
         mvs.iter()
             .copied()
             .flat_map(Movement::to_steps)
-            .scan(Some(Rope::new(length)), |r, dir| {
-                if let Some(ref rr) = r {
-                    let rrr = rr.move_head(dir)?;
-                    *r = Some(rrr.clone());
-                    Some(rrr.tail())
-                } else {
-                    None
-                }
+            .scan(Rope::new(length), |r, dir| {
+                r.move_head(dir, &ChebyshevAdjacent);
+                Some(r.tail())
             })
-            .collect::<Option<BTreeSet<Position>>>()?
+            .collect::<Option<VisitedSet>>()?
             .len(),
     )
 }
@@ -194,6 +492,7 @@ pub fn process_alternative(input: String, length: usize) -> Option<usize> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeSet;
 
     #[test]
     fn test_process() {
@@ -213,4 +512,117 @@ mod tests {
         assert_eq!(process(COMMANDS.to_string(), 9), Some(36));
         assert_eq!(process_alternative(COMMANDS.to_string(), 9), Some(36));
     }
+
+    #[test]
+    fn test_follow_rules() {
+        const COMMANDS: &str = include_str!("test-1.txt");
+
+        let visited = simulate(COMMANDS, 9, &ChebyshevAdjacent).unwrap();
+        assert_eq!(visited.len(), 10);
+        assert_eq!(visited[1].len(), 13);
+        assert_eq!(visited[9].len(), 1);
+
+        let diagonal = simulate("UR 3\nDL 1", 1, &ChebyshevAdjacent).unwrap();
+        assert_eq!(diagonal[0].len(), 4);
+        assert_eq!(diagonal[1].len(), 3);
+
+        let orthogonal = simulate("R 2\nU 1", 1, &ManhattanAdjacent).unwrap();
+        assert_eq!(
+            orthogonal[1].iter().collect::<BTreeSet<_>>(),
+            BTreeSet::from([
+                Position { x: 0, y: 0 },
+                Position { x: 1, y: 0 },
+                Position { x: 2, y: 0 }
+            ])
+        );
+
+        let slack = simulate(COMMANDS, 1, &Elastic { max_distance: 2 }).unwrap();
+        assert_eq!(slack[1].len(), 7);
+
+        struct Unsatisfiable;
+        impl FollowRule for Unsatisfiable {
+            fn is_close_enough(&self, _: Position, _: Position) -> bool {
+                false
+            }
+        }
+        let stacked = simulate("R 3\nU 2", 2, &Unsatisfiable).unwrap();
+        assert_eq!(stacked[2], stacked[0]);
+    }
+
+    #[test]
+    fn test_rendering() {
+        const SHORT: &str = include_str!("test-1.txt");
+        const LONG: &str = include_str!("test-2.txt");
+
+        let frames = snapshots(SHORT, 9, &ChebyshevAdjacent).unwrap();
+        let start = Bounds {
+            min: Position { x: 0, y: -4 },
+            max: Position { x: 5, y: 0 },
+        };
+        assert_eq!(frames.len(), 25);
+        assert_eq!(
+            render_knots(&frames[4], &start),
+            "......\n......\n......\n......\n4321H."
+        );
+        assert_eq!(
+            render_knots(&frames[8], &start),
+            "....H.\n....1.\n..432.\n.5....\n6....."
+        );
+        assert_eq!(
+            render_knots(&snapshots(SHORT, 1, &ChebyshevAdjacent).unwrap()[2], &start),
+            "......\n......\n......\n......\nsTH..."
+        );
+
+        let long_rope = (0..12).map(|x| Position { x, y: 0 }).collect::<Vec<_>>();
+        let line = Bounds {
+            min: Position { x: 0, y: 0 },
+            max: Position { x: 11, y: 0 },
+        };
+        assert_eq!(render_knots(&long_rope, &line), "H123456789**");
+
+        let frames = snapshots(LONG, 9, &ChebyshevAdjacent).unwrap();
+        let tail = frames.iter().map(|f| f[9]).collect::<VisitedSet>();
+        assert_eq!(
+            render_trail(&tail, &Bounds::of(tail.iter()).unwrap()),
+            "#.....................
+#.............###.....
+#............#...#....
+.#..........#.....#...
+..#..........#.....#..
+...#........#.......#.
+....#......s.........#
+.....#..............#.
+......#............#..
+.......#..........#...
+........#........#....
+.........########....."
+        );
+        assert_eq!(
+            trail_bounds(&frames),
+            Some(Bounds {
+                min: Position { x: -11, y: -15 },
+                max: Position { x: 14, y: 5 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        const COMMANDS: &str = "R 5000\nU 3000\nL 200\nDL 70\nD 7000\nR 3";
+
+        for rule in [
+            &ChebyshevAdjacent as &dyn FollowRule,
+            &ManhattanAdjacent,
+            &Elastic { max_distance: 3 },
+        ] {
+            let stepped = snapshots(COMMANDS, 9, rule)
+                .unwrap()
+                .iter()
+                .map(|knots| knots[9])
+                .collect::<VisitedSet>();
+            assert_eq!(simulate(COMMANDS, 9, rule).unwrap()[9], stepped);
+        }
+
+        assert_eq!(process("R 200000\nL 1".to_string(), 9), Some(199992));
+    }
 }
@@ -0,0 +1,35 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use day10::{program, Debugger, INSTRUCTIONS};
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or("input.txt".to_string());
+    let Ok(content) = fs::read_to_string(&path) else {
+        eprintln!("could not read {}", path);
+        return;
+    };
+    let cmds = match program(&content, INSTRUCTIONS) {
+        Ok(cmds) => cmds,
+        Err(error) => {
+            eprintln!("could not parse {}: {}", path, error);
+            return;
+        }
+    };
+
+    let mut debugger = Debugger::new(cmds);
+    let mut last = "step".to_string();
+
+    print!("> ");
+    io::stdout().flush().ok();
+    for line in io::stdin().lock().lines().map_while(Result::ok) {
+        match line.trim() {
+            "quit" | "q" => break,
+            "" => {}
+            cmd => last = cmd.to_string(),
+        }
+        println!("{}", debugger.command(&last));
+        print!("> ");
+        io::stdout().flush().ok();
+    }
+}
@@ -1,13 +1,18 @@
 use std::fs;
 
-use day10::process_crt;
+use day10::{process_crt, process_text};
 
 fn main() {
     let file = fs::read_to_string("input.txt");
 
     if let Ok(content) = file {
-        let result = process_crt(content);
+        let result = process_crt(content.clone());
 
         println!("{}", result.unwrap().1);
+
+        match process_text(content).unwrap() {
+            Ok(text) => println!("{}", text),
+            Err(glyphs) => glyphs.iter().for_each(|g| eprintln!("{}", g)),
+        }
     }
 }
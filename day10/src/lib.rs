@@ -2,75 +2,971 @@
 #![feature(iter_intersperse)]
 
 use nom::branch::alt;
-use nom::bytes::complete::tag;
-use nom::character::complete::line_ending;
-use nom::multi::separated_list1;
-use nom::sequence::preceded;
+use nom::character::complete::{alpha1, one_of, space1};
+use nom::combinator::{all_consuming, map_opt};
+use nom::multi::many0;
+use nom::sequence::{pair, preceded};
 use nom::*;
+use std::fmt;
 
+pub const REGISTER_NAMES: [char; 4] = ['x', 'y', 'z', 'w'];
+pub const X: Register = Register(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Immediate(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    Value,
+}
+
+/// An entry of the instruction table. `execute` runs once the last cycle is
+/// over and returns the offset of the next instruction relative to this one.
 #[derive(Debug)]
-enum Command {
-    Noop,
-    Addx(i32),
+pub struct Instruction {
+    pub opcode: &'static str,
+    pub operands: &'static [OperandKind],
+    pub cycles: u32,
+    pub execute: fn(&mut Cpu, &[Operand]) -> isize,
+}
+
+/// The default instruction set. Register arithmetic wraps around on overflow.
+pub const INSTRUCTIONS: &[Instruction] = &[
+    Instruction {
+        opcode: "noop",
+        operands: &[],
+        cycles: 1,
+        execute: |_, _| 1,
+    },
+    Instruction {
+        opcode: "addx",
+        operands: &[OperandKind::Value],
+        cycles: 2,
+        execute: |cpu, ops| {
+            cpu.registers[X.0] = cpu.registers[X.0].wrapping_add(cpu.read(ops[0]));
+            1
+        },
+    },
+    Instruction {
+        opcode: "set",
+        operands: &[OperandKind::Register, OperandKind::Value],
+        cycles: 1,
+        execute: |cpu, ops| {
+            cpu.write(ops[0], cpu.read(ops[1]));
+            1
+        },
+    },
+    Instruction {
+        opcode: "add",
+        operands: &[OperandKind::Register, OperandKind::Value],
+        cycles: 2,
+        execute: |cpu, ops| {
+            cpu.write(ops[0], cpu.read(ops[0]).wrapping_add(cpu.read(ops[1])));
+            1
+        },
+    },
+    Instruction {
+        opcode: "mul",
+        operands: &[OperandKind::Register, OperandKind::Value],
+        cycles: 3,
+        execute: |cpu, ops| {
+            cpu.write(ops[0], cpu.read(ops[0]).wrapping_mul(cpu.read(ops[1])));
+            1
+        },
+    },
+    Instruction {
+        opcode: "jmp",
+        operands: &[OperandKind::Value],
+        cycles: 1,
+        execute: |cpu, ops| cpu.read(ops[0]) as isize,
+    },
+    Instruction {
+        opcode: "jz",
+        operands: &[OperandKind::Value, OperandKind::Value],
+        cycles: 2,
+        execute: |cpu, ops| match cpu.read(ops[0]) {
+            0 => cpu.read(ops[1]) as isize,
+            _ => 1,
+        },
+    },
+    Instruction {
+        opcode: "jnz",
+        operands: &[OperandKind::Value, OperandKind::Value],
+        cycles: 2,
+        execute: |cpu, ops| match cpu.read(ops[0]) {
+            0 => 1,
+            _ => cpu.read(ops[1]) as isize,
+        },
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    instruction: &'static Instruction,
+    operands: Vec<Operand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cpu {
+    pub registers: [i32; REGISTER_NAMES.len()],
+    pub pc: isize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Clock {
+    pub cycle: u64,
+}
+
+/// Hardware attached to the machine, it gets to look at the CPU during every cycle.
+pub trait Device {
+    fn tick(&mut self, cycle: u64, cpu: &Cpu);
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub cpu: Cpu,
+    pub clock: Clock,
+    program: Vec<Command>,
+    progress: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sampling {
+    /// Every `interval` cycles, starting with cycle `first`.
+    Periodic {
+        first: u64,
+        interval: u64,
+    },
+    Cycles(Vec<u64>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalProbe {
+    pub schedule: Sampling,
+    pub register: Register,
+    pub sum: i64,
+}
+
+/// What the beam does once it moves past the last row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Stop,
+    Wrap,
+    Scroll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayConfig {
+    pub width: usize,
+    pub height: usize,
+    pub sprite_width: usize,
+    /// The register holding the sprite's center.
+    pub sprite: Register,
+    pub overflow: Overflow,
+}
+
+#[derive(Debug, Clone)]
+pub struct Crt {
+    config: DisplayConfig,
+    pixels: Vec<Vec<bool>>,
+}
+
+pub const GLYPH_WIDTH: usize = 4;
+pub const GLYPH_HEIGHT: usize = 6;
+
+/// The letters as drawn by the puzzle's CRT, glyphs are separated by a blank column.
+const FONT: &[(char, [&str; GLYPH_HEIGHT])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+    (' ', ["....", "....", "....", "....", "....", "...."]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownGlyph {
+    pub column: usize,
+    pub rows: Vec<String>,
+}
+
+impl fmt::Display for UnknownGlyph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "unknown glyph at column {}:", self.column)?;
+        write!(f, "{}", self.rows.join("\n"))
+    }
+}
+
+/// Reads the letters off a screen, rows missing below the glyph height count as dark.
+pub fn ocr<R: AsRef<[bool]>>(rows: &[R]) -> Result<String, Vec<UnknownGlyph>> {
+    let width = rows.iter().map(|r| r.as_ref().len()).max().unwrap_or(0);
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+
+    for column in (0..width).step_by(GLYPH_WIDTH + 1) {
+        let glyph = (0..GLYPH_HEIGHT)
+            .map(|y| {
+                (column..column + GLYPH_WIDTH)
+                    .map(|x| match rows.get(y).and_then(|r| r.as_ref().get(x)) {
+                        Some(true) => '#',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+
+        match FONT.iter().find(|(_, pattern)| pattern[..] == glyph[..]) {
+            Some((letter, _)) => text.push(*letter),
+            None => unknown.push(UnknownGlyph {
+                column,
+                rows: glyph,
+            }),
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(text)
+    } else {
+        Err(unknown)
+    }
+}
+
+impl Register {
+    pub fn from_name(name: char) -> Option<Register> {
+        REGISTER_NAMES.iter().position(|r| *r == name).map(Register)
+    }
+
+    pub fn name(&self) -> char {
+        REGISTER_NAMES[self.0]
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(r) => write!(f, "{}", r.name()),
+            Operand::Immediate(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.instruction.opcode)?;
+        for op in &self.operands {
+            write!(f, " {}", op)?;
+        }
+        Ok(())
+    }
+}
+
+impl OperandKind {
+    fn accepts(&self, operand: &Operand) -> bool {
+        match self {
+            Self::Register => matches!(operand, Operand::Register(_)),
+            Self::Value => true,
+        }
+    }
 }
 
 impl Command {
-    fn cycles_needed(&self) -> u32 {
+    pub fn new(instruction: &'static Instruction, operands: Vec<Operand>) -> Option<Self> {
+        (instruction.operands.len() == operands.len()
+            && instruction
+                .operands
+                .iter()
+                .zip(&operands)
+                .all(|(kind, op)| kind.accepts(op)))
+        .then_some(Command {
+            instruction,
+            operands,
+        })
+    }
+
+    pub fn cycles_needed(&self) -> u32 {
+        self.instruction.cycles
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        let mut registers = [0; REGISTER_NAMES.len()];
+        registers[X.0] = 1;
+        Cpu { registers, pc: 0 }
+    }
+}
+
+impl Cpu {
+    pub fn register(&self, register: Register) -> i32 {
+        self.registers[register.0]
+    }
+
+    pub fn read(&self, operand: Operand) -> i32 {
+        match operand {
+            Operand::Register(r) => self.register(r),
+            Operand::Immediate(value) => value,
+        }
+    }
+
+    fn write(&mut self, operand: Operand, value: i32) {
+        if let Operand::Register(r) = operand {
+            self.registers[r.0] = value;
+        }
+    }
+
+    fn execute(&mut self, cmd: &Command) {
+        self.pc += (cmd.instruction.execute)(self, &cmd.operands);
+    }
+}
+
+impl Clock {
+    fn tick(&mut self) -> u64 {
+        self.cycle += 1;
+        self.cycle
+    }
+}
+
+impl Machine {
+    pub fn new(program: Vec<Command>) -> Self {
+        Machine {
+            cpu: Cpu::default(),
+            clock: Clock::default(),
+            program,
+            progress: 0,
+        }
+    }
+
+    pub fn program(&self) -> &[Command] {
+        &self.program
+    }
+
+    /// The cycles the current instruction has already spent.
+    pub fn progress(&self) -> u32 {
+        self.progress
+    }
+
+    pub fn current(&self) -> Option<&Command> {
+        usize::try_from(self.cpu.pc)
+            .ok()
+            .and_then(|pc| self.program.get(pc))
+    }
+
+    pub fn halted(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// Runs a single cycle, the instruction takes effect at the end of its
+    /// last cycle. Returns false once halted.
+    pub fn tick(&mut self, devices: &mut [&mut dyn Device]) -> bool {
+        let Some(cmd) = usize::try_from(self.cpu.pc)
+            .ok()
+            .and_then(|pc| self.program.get(pc))
+        else {
+            return false;
+        };
+
+        let cycle = self.clock.tick();
+        for device in devices.iter_mut() {
+            device.tick(cycle, &self.cpu);
+        }
+
+        self.progress += 1;
+        if self.progress >= cmd.cycles_needed() {
+            self.progress = 0;
+            self.cpu.execute(cmd);
+        }
+
+        true
+    }
+
+    /// Runs the current instruction to completion, returns false once halted.
+    pub fn step(&mut self, devices: &mut [&mut dyn Device]) -> bool {
+        if !self.tick(devices) {
+            return false;
+        }
+        while self.progress > 0 && self.tick(devices) {}
+
+        true
+    }
+
+    pub fn run(&mut self, devices: &mut [&mut dyn Device]) {
+        while self.step(devices) {}
+    }
+}
+
+impl Sampling {
+    pub fn includes(&self, cycle: u64) -> bool {
         match self {
-            Command::Noop => 1,
-            Command::Addx(_) => 2,
+            Sampling::Periodic { first, interval } => cycle
+                .checked_sub(*first)
+                .is_some_and(|offset| offset.checked_rem(*interval).unwrap_or(offset) == 0),
+            Sampling::Cycles(cycles) => cycles.contains(&cycle),
         }
     }
+}
 
-    fn next_state(&self, state: i32, cycle: u32) -> i32 {
+impl SignalProbe {
+    pub fn new(schedule: Sampling) -> Self {
+        SignalProbe {
+            schedule,
+            register: X,
+            sum: 0,
+        }
+    }
+}
+
+impl Default for SignalProbe {
+    fn default() -> Self {
+        Self::new(Sampling::Periodic {
+            first: 20,
+            interval: 40,
+        })
+    }
+}
+
+impl Device for SignalProbe {
+    fn tick(&mut self, cycle: u64, cpu: &Cpu) {
+        if self.schedule.includes(cycle) {
+            self.sum += cycle as i64 * cpu.register(self.register) as i64;
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            width: 40,
+            height: 6,
+            sprite_width: 3,
+            sprite: X,
+            overflow: Overflow::Stop,
+        }
+    }
+}
+
+impl Crt {
+    pub fn new() -> Self {
+        Self::with_config(DisplayConfig::default())
+    }
+
+    pub fn with_config(config: DisplayConfig) -> Self {
+        Crt {
+            config,
+            pixels: vec![vec![false; config.width]; config.height],
+        }
+    }
+
+    pub fn config(&self) -> &DisplayConfig {
+        &self.config
+    }
+
+    pub fn render(&self) -> String {
+        self.pixels
+            .iter()
+            .map(|row| row.iter().map(|p| if *p { '#' } else { '.' }).collect())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// The screen as a plain PBM image, lit pixels are black.
+    pub fn to_pbm(&self) -> String {
+        let rows = self
+            .pixels
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|p| if *p { "1" } else { "0" })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "P1\n{} {}\n{}\n",
+            self.config.width,
+            self.config.height,
+            rows.join("\n")
+        )
+    }
+
+    pub fn read(&self) -> Result<String, Vec<UnknownGlyph>> {
+        ocr(&self.pixels)
+    }
+
+    /// The row and column the beam draws during the given cycle, if any.
+    pub fn position(&self, cycle: u64) -> Option<(usize, usize)> {
+        let position = (cycle as usize).checked_sub(1)?;
+        let row = position.checked_div(self.config.width)?;
+        let column = position % self.config.width;
+        let last = self.config.height.checked_sub(1)?;
+
+        match self.config.overflow {
+            Overflow::Stop => (row <= last).then_some((row, column)),
+            Overflow::Wrap => Some((row % self.config.height, column)),
+            Overflow::Scroll => Some((row.min(last), column)),
+        }
+    }
+
+    pub fn pixel(&self, row: usize, column: usize) -> Option<bool> {
+        self.pixels.get(row)?.get(column).copied()
+    }
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Crt {
+    fn tick(&mut self, cycle: u64, cpu: &Cpu) {
+        let Some((row, column)) = self.position(cycle) else {
+            return;
+        };
+
+        let scrolled = (cycle as usize - 1) / self.config.width >= self.config.height;
+        if self.config.overflow == Overflow::Scroll && column == 0 && scrolled {
+            self.pixels.rotate_left(1);
+            self.pixels[row].fill(false);
+        }
+
+        let width = self.config.sprite_width as i64;
+        let left = cpu.register(self.config.sprite) as i64 - (width - 1) / 2;
+        self.pixels[row][column] = (left..left + width).contains(&(column as i64));
+    }
+}
+
+fn operand(input: &str) -> IResult<&str, Operand> {
+    alt((
+        map_opt(one_of(&REGISTER_NAMES[..]), Register::from_name).map(Operand::Register),
+        character::complete::i32.map(Operand::Immediate),
+    ))(input)
+}
+
+fn command<'a>(
+    instructions: &'static [Instruction],
+) -> impl FnMut(&'a str) -> IResult<&'a str, Command> {
+    map_opt(
+        pair(alpha1, many0(preceded(space1, operand))),
+        move |(opcode, operands)| {
+            Command::new(instructions.iter().find(|i| i.opcode == opcode)?, operands)
+        },
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// One-based number of the offending line.
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} is not a valid command: {:?}",
+            self.line, self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a program against an instruction table. Every line has to be a
+/// command, only trailing whitespace at the end of the input is ignored.
+pub fn program(
+    input: &str,
+    instructions: &'static [Instruction],
+) -> Result<Vec<Command>, ParseError> {
+    input
+        .trim_end()
+        .split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            all_consuming(command(instructions))(line)
+                .map(|(_, cmd)| cmd)
+                .map_err(|_| ParseError {
+                    line: index + 1,
+                    text: line.to_string(),
+                })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stops during the given cycle.
+    Cycle(u64),
+    /// Stops during the first cycle the condition holds after it did not.
+    Register(Register, Comparison, i32),
+}
+
+/// Why the debugger handed back control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize),
+    Watch {
+        register: Register,
+        old: i32,
+        new: i32,
+    },
+    Halted,
+}
+
+/// The machine as seen during a single cycle, before the instruction in flight takes effect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleTrace {
+    pub cycle: u64,
+    pub cpu: Cpu,
+    /// The cycle of the instruction in flight, starting at 1.
+    pub progress: u32,
+    pub pixel: Option<(usize, usize)>,
+    pub lit: bool,
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    pub machine: Machine,
+    pub crt: Crt,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Register>,
+    last: Option<CycleTrace>,
+}
+
+#[derive(Default)]
+struct Recorder {
+    seen: Option<(u64, Cpu)>,
+}
+
+impl Device for Recorder {
+    fn tick(&mut self, cycle: u64, cpu: &Cpu) {
+        self.seen = Some((cycle, cpu.clone()));
+    }
+}
+
+impl Comparison {
+    fn parse(symbol: &str) -> Option<Comparison> {
+        Some(match symbol {
+            "==" => Self::Equal,
+            "!=" => Self::NotEqual,
+            "<" => Self::Less,
+            "<=" => Self::LessOrEqual,
+            ">" => Self::Greater,
+            ">=" => Self::GreaterOrEqual,
+            _ => return None,
+        })
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn holds(&self, a: i32, b: i32) -> bool {
         match self {
-            Self::Noop => state,
-            Self::Addx(incr) => state + if cycle == 1 { incr } else { &0 },
+            Self::Equal => a == b,
+            Self::NotEqual => a != b,
+            Self::Less => a < b,
+            Self::LessOrEqual => a <= b,
+            Self::Greater => a > b,
+            Self::GreaterOrEqual => a >= b,
         }
     }
 }
 
-fn commands(input: &str) -> IResult<&str, Vec<Command>> {
-    let (input, cmds) = separated_list1(
-        line_ending,
-        alt((
-            tag("noop").map(|_| Command::Noop),
-            preceded(tag("addx "), character::complete::i32).map(Command::Addx),
-        )),
-    )(input)?;
-    Ok((input, cmds))
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Cycle(cycle) => write!(f, "cycle {}", cycle),
+            Breakpoint::Register(r, cmp, value) => {
+                write!(f, "{} {} {}", r.name(), cmp.symbol(), value)
+            }
+        }
+    }
 }
 
-pub fn process_crt(input: String) -> Option<(i32, String)> {
-    let (_, cmds) = commands(&input).ok()?;
-    const SCREEN_WIDTH: usize = 40;
-    const SCREEN_HEIGHT: usize = 6;
-    let mut crt: [[bool; SCREEN_WIDTH]; SCREEN_HEIGHT] = [[false; SCREEN_WIDTH]; SCREEN_HEIGHT];
-
-    let mut counter: i32 = 1;
-    let mut cycle: usize = 0;
-    let mut sum_strength = 0;
-
-    for cmd in &cmds {
-        for c in 0..cmd.cycles_needed() {
-            let crt_column = cycle % SCREEN_WIDTH;
-            let crt_row = cycle / SCREEN_WIDTH;
-            let pixel_lit = ((counter - 1)..=(counter + 1)).contains(&(crt_column as i32));
-            crt[crt_row][crt_column] = pixel_lit;
-
-            cycle += 1;
-            if (cycle + 20) % 40 == 0 {
-                let strength = cycle as i32 * counter;
-                sum_strength += strength;
+impl Debugger {
+    pub fn new(program: Vec<Command>) -> Self {
+        Debugger {
+            machine: Machine::new(program),
+            crt: Crt::new(),
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            last: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    /// Stops execution whenever the register changes.
+    pub fn watch(&mut self, register: Register) {
+        if !self.watches.contains(&register) {
+            self.watches.push(register);
+        }
+    }
+
+    pub fn unwatch(&mut self, register: Register) {
+        self.watches.retain(|r| *r != register);
+    }
+
+    /// The most recent cycle.
+    pub fn last(&self) -> Option<&CycleTrace> {
+        self.last.as_ref()
+    }
+
+    /// Runs a single cycle.
+    pub fn tick(&mut self) -> Option<CycleTrace> {
+        let progress = self.machine.progress() + 1;
+        let mut recorder = Recorder::default();
+        self.machine.tick(&mut [&mut self.crt, &mut recorder]);
+
+        let (cycle, cpu) = recorder.seen?;
+        let pixel = self.crt.position(cycle);
+        let trace = CycleTrace {
+            cycle,
+            cpu,
+            progress,
+            pixel,
+            lit: pixel.and_then(|(row, column)| self.crt.pixel(row, column)) == Some(true),
+        };
+        self.last = Some(trace.clone());
+
+        Some(trace)
+    }
+
+    /// Runs the current instruction to completion.
+    pub fn step(&mut self) -> Vec<CycleTrace> {
+        let mut traces = Vec::new();
+        while let Some(trace) = self.tick() {
+            traces.push(trace);
+            if self.machine.progress() == 0 {
+                break;
             }
-            counter = cmd.next_state(counter, c);
         }
+        traces
     }
-    Some((
-        sum_strength,
-        crt.map(|row| row.map(|p| if p { "#" } else { "." }).join(""))
-            .join("\n"),
-    ))
+
+    /// Runs until a breakpoint or watch triggers or the program halts.
+    pub fn resume(&mut self) -> Stop {
+        loop {
+            let previous = self.last.clone();
+            let Some(trace) = self.tick() else {
+                return Stop::Halted;
+            };
+
+            if let Some(stop) = self.check(previous.as_ref(), &trace) {
+                return stop;
+            }
+        }
+    }
+
+    fn check(&self, previous: Option<&CycleTrace>, trace: &CycleTrace) -> Option<Stop> {
+        let hit = self.breakpoints.iter().position(|bp| match *bp {
+            Breakpoint::Cycle(cycle) => trace.cycle == cycle,
+            Breakpoint::Register(r, cmp, value) => {
+                cmp.holds(trace.cpu.register(r), value)
+                    && previous.is_none_or(|p| !cmp.holds(p.cpu.register(r), value))
+            }
+        });
+        if let Some(index) = hit {
+            return Some(Stop::Breakpoint(index));
+        }
+
+        let old = previous.map_or(Cpu::default(), |p| p.cpu.clone());
+        self.watches.iter().find_map(|&register| {
+            let (old, new) = (old.register(register), trace.cpu.register(register));
+            (old != new).then_some(Stop::Watch { register, old, new })
+        })
+    }
+
+    pub fn describe(&self, trace: &CycleTrace) -> String {
+        let cmd = usize::try_from(trace.cpu.pc)
+            .ok()
+            .and_then(|pc| self.machine.program().get(pc));
+        let registers = REGISTER_NAMES
+            .iter()
+            .zip(trace.cpu.registers)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let pixel = match trace.pixel {
+            Some((row, column)) => {
+                let shade = if trace.lit { '#' } else { '.' };
+                format!("pixel {},{} {}", row, column, shade)
+            }
+            None => "no pixel".to_string(),
+        };
+
+        format!(
+            "cycle {:>4} | pc {:>3} | {:<12} {}/{} | {} | {}",
+            trace.cycle,
+            trace.cpu.pc,
+            cmd.map_or(String::new(), |c| c.to_string()),
+            trace.progress,
+            cmd.map_or(0, |c| c.cycles_needed()),
+            registers,
+            pixel
+        )
+    }
+
+    /// Executes a textual debugger command and returns what should be shown.
+    pub fn command(&mut self, line: &str) -> String {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let register = |name: &str| {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Register::from_name(c),
+                _ => None,
+            }
+        };
+
+        match words[..] {
+            ["tick" | "t"] => match self.tick() {
+                Some(trace) => self.describe(&trace),
+                None => "halted".to_string(),
+            },
+            ["step" | "s"] => match self.step()[..] {
+                [] => "halted".to_string(),
+                ref traces => traces
+                    .iter()
+                    .map(|t| self.describe(t))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            },
+            ["continue" | "c"] => {
+                let reason = match self.resume() {
+                    Stop::Halted => return "halted".to_string(),
+                    Stop::Breakpoint(index) => {
+                        format!("breakpoint {}: {}", index, self.breakpoints[index])
+                    }
+                    Stop::Watch { register, old, new } => {
+                        format!("watch {}: {} -> {}", register.name(), old, new)
+                    }
+                };
+                match self.last() {
+                    Some(trace) => format!("{}\n{}", reason, self.describe(trace)),
+                    None => reason,
+                }
+            }
+            ["break" | "b", "cycle", cycle] => match cycle.parse() {
+                Ok(cycle) => {
+                    let index = self.add_breakpoint(Breakpoint::Cycle(cycle));
+                    format!("breakpoint {}: cycle {}", index, cycle)
+                }
+                Err(_) => format!("invalid cycle: {}", cycle),
+            },
+            ["break" | "b", name, symbol, value] => {
+                match (register(name), Comparison::parse(symbol), value.parse()) {
+                    (Some(r), Some(cmp), Ok(value)) => {
+                        let bp = Breakpoint::Register(r, cmp, value);
+                        format!("breakpoint {}: {}", self.add_breakpoint(bp), bp)
+                    }
+                    _ => format!("invalid condition: {} {} {}", name, symbol, value),
+                }
+            }
+            ["delete" | "d", index] => {
+                match index.parse().ok().and_then(|i| self.remove_breakpoint(i)) {
+                    Some(bp) => format!("deleted {}", bp),
+                    None => format!("no breakpoint {}", index),
+                }
+            }
+            ["breakpoints"] => self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .map(|(i, bp)| format!("{}: {}", i, bp))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ["watch" | "w", name] => match register(name) {
+                Some(r) => {
+                    self.watch(r);
+                    format!("watching {}", r.name())
+                }
+                None => format!("unknown register: {}", name),
+            },
+            ["unwatch", name] => match register(name) {
+                Some(r) => {
+                    self.unwatch(r);
+                    format!("stopped watching {}", r.name())
+                }
+                None => format!("unknown register: {}", name),
+            },
+            ["screen"] => self.crt.render(),
+            _ => format!("unknown command: {}", line.trim()),
+        }
+    }
+}
+
+fn commands(input: &str) -> Result<Vec<Command>, ParseError> {
+    program(input, INSTRUCTIONS)
+}
+
+pub fn process_crt(input: String) -> Option<(i32, String)> {
+    let cmds = commands(&input).ok()?;
+
+    let mut probe = SignalProbe::default();
+    let mut crt = Crt::new();
+    Machine::new(cmds).run(&mut [&mut probe, &mut crt]);
+
+    Some((probe.sum as i32, crt.render()))
+}
+
+pub fn process_text(input: String) -> Option<Result<String, Vec<UnknownGlyph>>> {
+    let cmds = commands(&input).ok()?;
+
+    let mut crt = Crt::new();
+    Machine::new(cmds).run(&mut [&mut crt]);
+
+    Some(crt.read())
 }
 
 #[cfg(test)]
@@ -89,4 +985,157 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_machine() {
+        let cmds = program("set y 5\naddx 2\nadd y -1\njnz y -2\nmul x 3", INSTRUCTIONS).unwrap();
+        let mut machine = Machine::new(cmds);
+        machine.run(&mut []);
+
+        assert!(machine.halted());
+        assert_eq!(machine.cpu.registers, [33, 0, 0, 0]);
+        assert_eq!(machine.clock.cycle, 1 + 5 * 6 + 3);
+
+        let cmds = program(
+            "set x 2147483647\nadd x 1\nset y 65536\nmul y y\naddx -1",
+            INSTRUCTIONS,
+        );
+        let mut machine = Machine::new(cmds.unwrap());
+        machine.run(&mut []);
+        assert_eq!(machine.cpu.registers, [i32::MAX, 0, 0, 0]);
+
+        assert!(program("addx y z", INSTRUCTIONS).is_err());
+        assert!(program("set 1 2", INSTRUCTIONS).is_err());
+        assert!(program("halt", INSTRUCTIONS).is_err());
+        assert!(program("", INSTRUCTIONS).is_err());
+    }
+
+    #[test]
+    fn test_whole_input() {
+        assert_eq!(
+            program("noop\naddx 5\n\n  \n", INSTRUCTIONS).map(|c| c.len()),
+            Ok(2)
+        );
+        assert_eq!(
+            program("noop\r\naddx 5\r\n", INSTRUCTIONS).map(|c| c.len()),
+            Ok(2)
+        );
+        assert_eq!(
+            program("noop\nhalt\naddx 5", INSTRUCTIONS).err(),
+            Some(ParseError {
+                line: 2,
+                text: "halt".to_string()
+            })
+        );
+        assert_eq!(
+            program("noop \naddx 5", INSTRUCTIONS).err(),
+            Some(ParseError {
+                line: 1,
+                text: "noop ".to_string()
+            })
+        );
+        assert_eq!(
+            program("noop\naddx 5 junk", INSTRUCTIONS)
+                .err()
+                .map(|e| e.line),
+            Some(2)
+        );
+        assert_eq!(process_crt("addx 1\nbogus\naddx 5".to_string()), None);
+    }
+
+    #[test]
+    fn test_ocr() {
+        let screen = |text: &str| {
+            text.lines()
+                .map(|l| l.chars().map(|c| c == '#').collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ocr(&screen(
+                "#..#.####.#....#.....##.\n\
+                 #..#.#....#....#....#..#\n\
+                 ####.###..#....#....#..#\n\
+                 #..#.#....#....#....#..#\n\
+                 #..#.#....#....#....#..#\n\
+                 #..#.####.####.####..##."
+            )),
+            Ok("HELLO".to_string())
+        );
+
+        let errors = process_text(include_str!("test.txt").to_string())
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(errors.len(), 8);
+        assert_eq!(errors[1].column, 5);
+        assert_eq!(errors[1].rows[0], "#..#");
+    }
+
+    #[test]
+    fn test_debugger() {
+        let cmds = commands(include_str!("test.txt")).unwrap();
+        let mut debugger = Debugger::new(cmds);
+
+        let traces = debugger.step();
+        assert_eq!(traces.len(), 2);
+        assert_eq!((traces[1].progress, traces[1].cpu.register(X)), (2, 1));
+        assert_eq!(debugger.machine.cpu.register(X), 16);
+
+        debugger.add_breakpoint(Breakpoint::Cycle(20));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(0));
+        assert_eq!(
+            debugger.last().map(|t| (t.cycle, t.cpu.register(X))),
+            Some((20, 21))
+        );
+
+        debugger.add_breakpoint(Breakpoint::Register(X, Comparison::Less, 0));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(1));
+        assert!(debugger.last().unwrap().cpu.register(X) < 0);
+
+        debugger.remove_breakpoint(1);
+        debugger.watch(X);
+        let before = debugger.last().unwrap().cpu.register(X);
+        assert!(matches!(debugger.resume(), Stop::Watch { old, .. } if old == before));
+
+        assert_eq!(debugger.command("break x >= 30"), "breakpoint 1: x >= 30");
+        assert!(debugger.command("tick").starts_with("cycle "));
+        assert_eq!(debugger.command("unwatch x"), "stopped watching x");
+        debugger.command("delete 0");
+        debugger.command("delete 0");
+        assert_eq!(debugger.command("continue"), "halted");
+        assert_eq!(debugger.command("screen"), include_str!("assert.txt"));
+    }
+
+    #[test]
+    fn test_display_config() {
+        let cmds = commands(include_str!("test.txt")).unwrap();
+        let expected = include_str!("assert.txt").lines().collect::<Vec<_>>();
+        let short = |overflow| {
+            let mut crt = Crt::with_config(DisplayConfig {
+                height: 4,
+                overflow,
+                ..DisplayConfig::default()
+            });
+            Machine::new(cmds.clone()).run(&mut [&mut crt]);
+            crt.render()
+        };
+
+        assert_eq!(short(Overflow::Stop), expected[0..4].join("\n"));
+        assert_eq!(
+            short(Overflow::Wrap),
+            [4, 5, 2, 3].map(|r| expected[r]).join("\n")
+        );
+        assert_eq!(short(Overflow::Scroll), expected[2..6].join("\n"));
+
+        let mut probe = SignalProbe::new(Sampling::Cycles(vec![20, 60]));
+        let mut thin = Crt::with_config(DisplayConfig {
+            width: 4,
+            height: 1,
+            sprite_width: 1,
+            ..DisplayConfig::default()
+        });
+        Machine::new(cmds).run(&mut [&mut probe, &mut thin]);
+        assert_eq!(probe.sum, 420 + 1140);
+        assert_eq!(thin.to_pbm(), "P1\n4 1\n0 1 0 0\n");
+    }
 }
@@ -3,19 +3,40 @@
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::line_ending;
+use nom::character::complete::one_of;
+use nom::character::complete::space0;
 use nom::combinator::value;
+use nom::multi::fold_many0;
 use nom::multi::separated_list1;
+use nom::sequence::delimited;
 use nom::sequence::pair;
 use nom::sequence::preceded;
 use nom::*;
+use num::integer::lcm;
+use num::{BigUint, CheckedAdd, CheckedMul, CheckedSub, Zero};
 use std::collections::BinaryHeap;
 use std::collections::VecDeque;
+use std::ops::{Div, Rem};
 
+/// How worry levels are kept in check after every inspection.
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Calming {
+    /// Relief divides the worry level, the puzzle's first part uses 3.
+    Divide(u64),
+    /// Only the remainder modulo the least common multiple of all divisors is kept,
+    /// which does not change any monkey's decision.
+    ModuloLcm,
+    /// Worry levels grow unchecked.
+    None,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
 enum Operation {
-    Multiply(u64),
-    Add(u64),
-    Square,
+    Old,
+    Constant(u64),
+    Add(Box<Operation>, Box<Operation>),
+    Subtract(Box<Operation>, Box<Operation>),
+    Multiply(Box<Operation>, Box<Operation>),
 }
 
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
@@ -26,27 +47,77 @@ struct Test {
 }
 
 #[derive(Eq, PartialEq, Debug)]
-struct Monkey {
+struct Monkey<W = u64> {
     name: u64,
-    items: VecDeque<u64>,
+    items: VecDeque<W>,
     operation: Operation,
     test: Test,
     inspection_count: u64,
 }
 
+/// A number type worry levels can be tracked in.
+trait Worry:
+    Sized
+    + Clone
+    + From<u64>
+    + Zero
+    + CheckedAdd
+    + CheckedSub
+    + CheckedMul
+    + Div<u64, Output = Self>
+    + Rem<u64, Output = Self>
+{
+    fn apply_modulo(operation: &Operation, old: &Self, modulus: u64) -> Option<Self> {
+        Some(operation.apply(old)? % modulus)
+    }
+}
+
+impl Worry for u64 {
+    fn apply_modulo(operation: &Operation, old: &Self, modulus: u64) -> Option<Self> {
+        Some(operation.apply_modulo(*old, modulus))
+    }
+}
+
+impl Worry for BigUint {}
+
 impl Operation {
-    fn apply(&self, number: u64) -> u64 {
+    /// The exact new worry level, `None` if it overflows or drops below zero.
+    fn apply<W: Worry>(&self, number: &W) -> Option<W> {
         match self {
-            Self::Multiply(factor) => number * factor,
-            Self::Square => number * number,
-            Self::Add(sum) => number + sum,
+            Self::Old => Some(number.clone()),
+            Self::Constant(c) => Some(W::from(*c)),
+            Self::Add(a, b) => a.apply(number)?.checked_add(&b.apply(number)?),
+            Self::Subtract(a, b) => a.apply(number)?.checked_sub(&b.apply(number)?),
+            Self::Multiply(a, b) => a.apply(number)?.checked_mul(&b.apply(number)?),
         }
     }
+
+    /// The new worry level modulo `modulus`. Only addition, subtraction and
+    /// multiplication are used, so the result is congruent to the exact one
+    /// whenever that one is not negative.
+    fn apply_modulo(&self, number: u64, modulus: u64) -> u64 {
+        let m = modulus as u128;
+        let result = match self {
+            Self::Old => number as u128 % m,
+            Self::Constant(c) => *c as u128 % m,
+            Self::Add(a, b) => {
+                a.apply_modulo(number, modulus) as u128 + b.apply_modulo(number, modulus) as u128
+            }
+            Self::Subtract(a, b) => {
+                a.apply_modulo(number, modulus) as u128 + m
+                    - b.apply_modulo(number, modulus) as u128
+            }
+            Self::Multiply(a, b) => {
+                a.apply_modulo(number, modulus) as u128 * b.apply_modulo(number, modulus) as u128
+            }
+        };
+        (result % m) as u64
+    }
 }
 
 impl Test {
-    fn decide(&self, number: u64) -> usize {
-        if number % self.divisible == 0 {
+    fn decide<W: Worry>(&self, number: &W) -> usize {
+        if (number.clone() % self.divisible).is_zero() {
             self.true_target
         } else {
             self.false_target
@@ -63,17 +134,45 @@ fn items(input: &str) -> IResult<&str, VecDeque<u64>> {
     Ok((input, VecDeque::from(numbers)))
 }
 
-fn operation(input: &str) -> IResult<&str, Operation> {
-    preceded(
-        pair(line_ending, tag("  Operation: new = old ")),
+fn factor(input: &str) -> IResult<&str, Operation> {
+    delimited(
+        space0,
         alt((
-            value(Operation::Square, tag("* old")),
-            preceded(tag("+ "), character::complete::u64).map(Operation::Add),
-            preceded(tag("* "), character::complete::u64).map(Operation::Multiply),
+            value(Operation::Old, tag("old")),
+            character::complete::u64.map(Operation::Constant),
+            delimited(tag("("), expression, tag(")")),
         )),
+        space0,
     )(input)
 }
 
+fn term(input: &str) -> IResult<&str, Operation> {
+    let (input, first) = factor(input)?;
+
+    fold_many0(
+        preceded(tag("*"), factor),
+        move || first.clone(),
+        |a, b| Operation::Multiply(Box::new(a), Box::new(b)),
+    )(input)
+}
+
+fn expression(input: &str) -> IResult<&str, Operation> {
+    let (input, first) = term(input)?;
+
+    fold_many0(
+        pair(one_of("+-"), term),
+        move || first.clone(),
+        |a, (op, b)| match op {
+            '+' => Operation::Add(Box::new(a), Box::new(b)),
+            _ => Operation::Subtract(Box::new(a), Box::new(b)),
+        },
+    )(input)
+}
+
+fn operation(input: &str) -> IResult<&str, Operation> {
+    preceded(pair(line_ending, tag("  Operation: new =")), expression)(input)
+}
+
 fn test(input: &str) -> IResult<&str, Test> {
     let (input, divisible) = preceded(
         pair(line_ending, tag("  Test: divisible by ")),
@@ -122,9 +221,29 @@ fn monkeys(input: &str) -> IResult<&str, Vec<Monkey>> {
     Ok((input, mks))
 }
 
-pub fn process(input: String, rounds: u64, worried: bool) -> Option<u64> {
-    let (_, mut mnks) = monkeys(&input).ok()?;
-    let lowest_common_denominator: u64 = mnks.iter().map(|m| m.test.divisible).product();
+impl Monkey {
+    fn convert<W: Worry>(self) -> Monkey<W> {
+        Monkey {
+            name: self.name,
+            items: self.items.into_iter().map(W::from).collect(),
+            operation: self.operation,
+            test: self.test,
+            inspection_count: self.inspection_count,
+        }
+    }
+}
+
+/// How often each monkey inspected an item, `None` if a worry level does not fit `W`.
+fn simulate<W: Worry>(mnks: Vec<Monkey>, rounds: u64, calming: Calming) -> Option<Vec<u64>> {
+    let mut mnks = mnks
+        .into_iter()
+        .map(Monkey::convert::<W>)
+        .collect::<Vec<_>>();
+    let lowest_common_multiple = mnks.iter().map(|m| m.test.divisible).fold(1, lcm);
+
+    if calming == Calming::Divide(0) {
+        return None;
+    }
 
     for _ in 0..rounds {
         // dbg!(round);
@@ -132,27 +251,56 @@ pub fn process(input: String, rounds: u64, worried: bool) -> Option<u64> {
             // dbg!(&monkey.items);
             while let Some(item) = mnks[monkey_number].items.pop_front() {
                 // dbg!(item);
-                let changed = mnks[monkey_number].operation.apply(item);
                 mnks[monkey_number].inspection_count += 1;
-                let calmed = if worried {
-                    changed % lowest_common_denominator
-                } else {
-                    changed / 3
+                let operation = &mnks[monkey_number].operation;
+                let calmed = match calming {
+                    Calming::Divide(k) => operation.apply(&item)? / k,
+                    Calming::ModuloLcm => {
+                        W::apply_modulo(operation, &item, lowest_common_multiple)?
+                    }
+                    Calming::None => operation.apply(&item)?,
                 };
 
-                let new_monkey = mnks[monkey_number].test.decide(calmed);
+                let new_monkey = mnks[monkey_number].test.decide(&calmed);
                 mnks[new_monkey].items.push_back(calmed);
             }
         }
     }
 
-    let mut inspection_counts = mnks
-        .iter()
-        .map(|m| m.inspection_count)
-        .collect::<BinaryHeap<_>>();
+    Some(mnks.iter().map(|m| m.inspection_count).collect())
+}
+
+/// The inspection counts with worry levels kept in `u64`, `None` once one overflows.
+pub fn inspections(input: &str, rounds: u64, calming: Calming) -> Option<Vec<u64>> {
+    let (_, mnks) = monkeys(input).ok()?;
+    simulate::<u64>(mnks, rounds, calming)
+}
+
+/// The inspection counts with exact worry levels, slow but never overflowing.
+pub fn inspections_exact(input: &str, rounds: u64, calming: Calming) -> Option<Vec<u64>> {
+    let (_, mnks) = monkeys(input).ok()?;
+    simulate::<BigUint>(mnks, rounds, calming)
+}
+
+fn monkey_business(inspection_counts: Vec<u64>) -> Option<u64> {
+    let mut inspection_counts = inspection_counts.into_iter().collect::<BinaryHeap<_>>();
     Some(inspection_counts.pop()? * inspection_counts.pop()?)
 }
 
+pub fn process_with(input: String, rounds: u64, calming: Calming) -> Option<u64> {
+    monkey_business(inspections(&input, rounds, calming)?)
+}
+
+pub fn process(input: String, rounds: u64, worried: bool) -> Option<u64> {
+    let calming = if worried {
+        Calming::ModuloLcm
+    } else {
+        Calming::Divide(3)
+    };
+
+    process_with(input, rounds, calming)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +312,42 @@ mod tests {
         assert_eq!(process(COMMANDS.to_string(), 20, false), Some(10605));
         assert_eq!(process(COMMANDS.to_string(), 10000, true), Some(2713310158));
     }
+
+    #[test]
+    fn test_operations() {
+        let (_, op) = expression(" (old + 3) * old - 2 * old + old * (1 + 1)").unwrap();
+        assert_eq!(op.apply(&5u64), Some(40));
+        assert_eq!(op.apply_modulo(5, 7), 40 % 7);
+        assert_eq!(op.apply(&(u64::MAX / 2)), None);
+        assert_eq!(op.apply_modulo(u64::MAX / 2, 23), {
+            let old = (u64::MAX / 2) as u128;
+            (((old + 3) * old - 2 * old + old * 2) % 23) as u64
+        });
+
+        let (_, op) = expression(" old - 10").unwrap();
+        assert_eq!(op.apply(&3u64), None);
+
+        let doubled = include_str!("test.txt").replace("old * old", "old + old");
+        assert_eq!(process(doubled, 20, false), Some(10600));
+    }
+
+    #[test]
+    fn test_calming() {
+        const COMMANDS: &str = include_str!("test.txt");
+
+        assert_eq!(
+            inspections(COMMANDS, 20, Calming::Divide(3)),
+            inspections_exact(COMMANDS, 20, Calming::Divide(3))
+        );
+        assert_eq!(inspections(COMMANDS, 20, Calming::Divide(2)), None);
+        assert_eq!(
+            inspections_exact(COMMANDS, 20, Calming::Divide(2)),
+            Some(vec![101, 95, 10, 104])
+        );
+
+        let exact = inspections_exact(COMMANDS, 20, Calming::None);
+        assert_eq!(inspections(COMMANDS, 20, Calming::ModuloLcm), exact);
+        assert_eq!(inspections(COMMANDS, 20, Calming::None), None);
+        assert_eq!(exact, Some(vec![99, 97, 8, 103]));
+    }
 }
@@ -1,11 +1,11 @@
 #![feature(iter_array_chunks)]
 #![feature(let_chains)]
 #![feature(array_windows)]
-#![feature(array_chunks)]
 #![feature(iterator_try_reduce)]
 #![feature(iter_intersperse)]
 #![feature(step_trait)]
 
+use day04::IntervalSet;
 use nom::bytes::complete::tag;
 use nom::character::complete::line_ending;
 use nom::combinator::map;
@@ -107,38 +107,18 @@ pub fn process(input: String, line: i32) -> Option<usize> {
     );
     Some(ruled_out.len() - 1)
 }
-fn ranges_overlap<T: num::Integer + num::Zero + Copy + Clone>(
-    a: &std::ops::RangeInclusive<T>,
-    b: &std::ops::RangeInclusive<T>,
-) -> bool {
-    T::max(*a.start(), *b.start()) - T::min(*a.end(), *b.end()) <= T::zero()
-}
-fn ranges_merge<T: num::Integer + num::Zero + num::One + Copy + Clone>(
-    a: std::ops::RangeInclusive<T>,
-    b: std::ops::RangeInclusive<T>,
-) -> Result<std::ops::RangeInclusive<T>, std::ops::Range<T>> {
-    if ranges_overlap(&a, &b) {
-        Ok(*a.start().min(b.start())..=*(a.end().max(b.end())))
-    } else {
-        Err((*a.end().min(b.end()) + T::one())..*(a.start().max(b.start())))
-    }
-}
 
 pub fn process_search(input: String, limit: i32) -> Option<u64> {
     let (_, readings) = sensor_readings(&input).ok()?;
     let rng: Vec<i32> = (0..=limit).collect();
     rng.iter().find_map(|&line| {
-        let mut ranges = readings
-            .iter()
-            .flat_map(|r| r.reachable_range_at_y(line))
-            .collect::<Vec<_>>();
-        ranges.sort_by_key(|r| *r.start());
-
-        if let Err(e) = ranges.iter().cloned().try_reduce(ranges_merge) {
-            return Some(e.start as u64 * 4000000 + line as u64);
-        }
+        let ranges =
+            IntervalSet::from_iter(readings.iter().flat_map(|r| r.reachable_range_at_y(line)));
 
-        None
+        ranges
+            .gaps()
+            .min()
+            .map(|x| x as u64 * 4000000 + line as u64)
     })
 }
 